@@ -0,0 +1,62 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+/// Keeps track of the last activity (file operations, log streams, etc.) of each playbook.
+#[derive(Debug, Default)]
+pub struct ActivityTracker {
+    playbooks: RwLock<HashMap<Uuid, Activity>>,
+}
+
+#[derive(Debug)]
+struct Activity {
+    last: Instant,
+    stopped: bool,
+}
+
+impl ActivityTracker {
+    /// Record an activity of the playbook now.
+    pub fn touch(&self, id: Uuid) {
+        self.playbooks.write().unwrap().insert(id, Activity { last: Instant::now(), stopped: false });
+    }
+
+    /// Start tracking the playbook as if it was just used, unless it's already tracked,
+    /// e.g. the playbooks created before a restart.
+    pub fn seed(&self, id: Uuid) {
+        self.playbooks.write().unwrap().entry(id).or_insert_with(|| Activity { last: Instant::now(), stopped: false });
+    }
+
+    /// Mark the playbook as stopped because of inactivity, until its next activity.
+    pub fn mark_stopped(&self, id: Uuid) {
+        if let Some(activity) = self.playbooks.write().unwrap().get_mut(&id) {
+            activity.stopped = true;
+        }
+    }
+
+    /// Stop tracking the playbook, e.g. when it's deleted.
+    pub fn forget(&self, id: Uuid) {
+        self.playbooks.write().unwrap().remove(&id);
+    }
+
+    /// Returns how long each tracked playbook has been idle, and whether it has been stopped.
+    pub fn idle(&self) -> Vec<(Uuid, Duration, bool)> {
+        let now = Instant::now();
+        self.playbooks.read().unwrap().iter().map(|(id, a)| (*id, now.duration_since(a.last), a.stopped)).collect()
+    }
+}
@@ -12,25 +12,180 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt::Debug;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
+use axum::extract::connect_info::Connected;
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use axum::middleware::{from_fn, from_fn_with_state};
+use axum::serve::{IncomingStream, Listener};
+use axum::Router;
+use futures::{future, FutureExt};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tracing::{info, warn};
+
+use crate::config::Config;
 use crate::context::Context;
-use crate::{routes, swagger};
+use crate::listener::{self, ClientAddr, TlsListener};
+use crate::middleware::idempotency::{idempotency, IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAYED_HEADER};
+use crate::middleware::identity::identify;
+use crate::middleware::rate_limit::{rate_limit, RATE_LIMIT_LIMIT, RATE_LIMIT_REMAINING, RATE_LIMIT_RESET};
+use crate::middleware::request_id::{request_id, REQUEST_ID_HEADER};
+use crate::user::USER_HEADER;
+use crate::{monitor, reaper, routes, shutdown, swagger, telemetry};
+
+pub async fn run(ctx: Arc<Context>) -> anyhow::Result<()> {
+    // Record the metrics from now on, they are exposed on `/metrics`.
+    monitor::install()?;
 
-pub async fn run(ctx: Arc<Context>) {
-    let port = ctx.config.port;
+    // Stop and delete the abandoned playbooks, and the ones out of running time, in the background.
+    if ctx.config.idle_timeout > 0 || ctx.config.playbook_ttl > 0 || ctx.config.quota_running_seconds > 0 {
+        tokio::spawn(reaper::run(ctx.clone()));
+    }
 
     // build our application with a route
-    let app = routes::build().merge(swagger::build()).with_state(ctx);
+    let mut app = routes::build(&ctx.config)
+        .layer(from_fn_with_state(ctx.clone(), idempotency))
+        .layer(from_fn_with_state(ctx.clone(), rate_limit))
+        .layer(from_fn(monitor::track))
+        .layer(from_fn(telemetry::trace))
+        .layer(from_fn_with_state(ctx.clone(), identify))
+        .merge(swagger::build())
+        // the request id covers the documentation too, so every response carries one
+        .layer(from_fn(request_id))
+        .with_state(ctx.clone());
+
+    // compress the responses, except the event streams which are skipped by the default predicate
+    if ctx.config.compression {
+        app = app.layer(CompressionLayer::new().gzip(true).br(true).zstd(true));
+    }
+
+    // and allow the configured origins to call this API from the browsers
+    if let Some(cors) = cors(&ctx.config) {
+        app = app.layer(cors);
+    }
+
+    // run our app with hyper, and serve it over HTTP, or HTTPS if a certificate is configured
+    let addr = SocketAddr::new(ctx.config.bind_address, ctx.config.port);
+    let tcp = listener::bind_tcp(addr).await?;
+    let tcp = match (&ctx.config.tls_cert, &ctx.config.tls_key) {
+        (Some(cert), Some(key)) => {
+            let acceptor = listener::tls_acceptor(cert.clone(), key.clone())?;
+            info!("Listening on https://{}", addr);
+            let listener = TlsListener::new(tcp, acceptor, ctx.shutdown.signalled())?;
+            serve(ctx.clone(), listener, app.clone()).boxed()
+        }
+        _ => {
+            info!("Listening on http://{}", addr);
+            serve(ctx.clone(), tcp, app.clone()).boxed()
+        }
+    };
+
+    // and on the Unix domain socket as well, if configured
+    let unix = match &ctx.config.unix_socket {
+        #[cfg(unix)]
+        Some(path) => {
+            let unix = listener::bind_unix(path)?;
+            info!("Listening on unix:{}", path.display());
+            serve(ctx.clone(), unix, app).boxed()
+        }
+        #[cfg(not(unix))]
+        Some(_) => anyhow::bail!("Unix domain sockets are not supported on this platform"),
+        None => future::ok(()).boxed(),
+    };
 
-    // run our app with hyper, and serve it over HTTP
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    // On SIGINT or SIGTERM the readiness probe fails from then on, and the requests are still served
+    // for the grace period, until the load balancers stop routing here, before draining.
+    tokio::spawn({
+        let ctx = ctx.clone();
+        async move {
+            shutdown::signal().await;
+            let grace_period = Duration::from_secs(ctx.config.shutdown_grace_period);
+            info!("Shutting down, serving for {}s more before draining...", grace_period.as_secs());
+            ctx.shutdown.announce();
 
-    // Run this server for ... forever!
-    if let Err(err) = axum::serve(listener, app).await {
-        tracing::error!("Server error: {}", err);
-        std::process::exit(1)
+            // A second signal doesn't wait any longer.
+            tokio::select! {
+                _ = tokio::time::sleep(grace_period) => {}
+                _ = shutdown::signal() => {}
+            }
+            info!("Draining the in-flight requests...");
+            ctx.shutdown.trigger();
+        }
+    });
+
+    // Run this server until it's drained, or the drain timeout is over.
+    let drain_timeout = Duration::from_secs(ctx.config.drain_timeout);
+    tokio::select! {
+        result = future::try_join(tcp, unix) => {
+            result?;
+        }
+        _ = async {
+            ctx.shutdown.signalled().await;
+            tokio::time::sleep(drain_timeout).await;
+        } => warn!("The requests still in flight after {}s are cut off", drain_timeout.as_secs()),
+    }
+
+    Ok(())
+}
+
+/// Build the CORS layer from the allowed origins, none if no origin is allowed.
+fn cors(config: &Config) -> Option<CorsLayer> {
+    let origins: Vec<&str> = config.cors_allowed_origins.iter().map(|o| o.trim()).filter(|o| !o.is_empty()).collect();
+    if origins.is_empty() {
+        return None;
     }
+
+    let allow_origin = if origins.contains(&"*") {
+        AllowOrigin::from(Any)
+    } else {
+        let origins = origins.iter().filter_map(|o| match HeaderValue::from_str(o) {
+            Ok(origin) => Some(origin),
+            Err(_) => {
+                warn!("Ignoring the invalid CORS origin: {}", o);
+                None
+            }
+        });
+        AllowOrigin::list(origins)
+    };
+
+    Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+            .allow_headers([
+                header::CONTENT_TYPE,
+                header::AUTHORIZATION,
+                HeaderName::from_static("last-event-id"),
+                HeaderName::from_static("traceparent"),
+                HeaderName::from_static(IDEMPOTENCY_KEY_HEADER),
+                HeaderName::from_static(REQUEST_ID_HEADER),
+                HeaderName::from_static(USER_HEADER),
+            ])
+            .expose_headers([
+                header::LOCATION,
+                header::RETRY_AFTER,
+                RATE_LIMIT_LIMIT,
+                RATE_LIMIT_REMAINING,
+                RATE_LIMIT_RESET,
+                HeaderName::from_static(REQUEST_ID_HEADER),
+                HeaderName::from_static(IDEMPOTENT_REPLAYED_HEADER),
+            ])
+            .max_age(Duration::from_secs(3600)),
+    )
+}
+
+/// Serve the app on the listener until the server is drained, telling the handlers who the clients are.
+async fn serve<L>(ctx: Arc<Context>, listener: L, app: Router) -> std::io::Result<()>
+where
+    L: Listener,
+    L::Addr: Debug,
+    for<'a> ClientAddr: Connected<IncomingStream<'a, L>>,
+{
+    axum::serve(listener, app.into_make_service_with_connect_info::<ClientAddr>())
+        .with_graceful_shutdown(ctx.shutdown.signalled())
+        .await
 }
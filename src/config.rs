@@ -12,6 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use amp_common::scm::driver::github::constants::GITHUB_ENDPOINT;
+use ipnet::IpNet;
+
+use crate::upstream::MAX_BACKOFF;
+
 /// The configuration parameters for the application.
 ///
 /// These can either be passed on the command line, or pulled from environment variables.
@@ -32,10 +40,256 @@ pub struct Config {
     #[clap(long, env = "AMP_PORT")]
     pub port: u16,
 
+    /// The address the server listens on, `0.0.0.0` for all IPv4 interfaces or `::` for IPv6 as well.
+    #[clap(long, env = "AMP_BIND_ADDRESS", default_value = "0.0.0.0")]
+    pub bind_address: IpAddr,
+
+    /// The PEM certificate chain to serve HTTPS with, reloaded when the file changes.
+    #[clap(long, env = "AMP_TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// The PEM private key of the certificate.
+    #[clap(long, env = "AMP_TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// The path of a Unix domain socket to listen on as well, e.g. for a sidecar proxy.
+    #[clap(long, env = "AMP_UNIX_SOCKET")]
+    pub unix_socket: Option<PathBuf>,
+
     /// The Amphitheatre Server.
     #[clap(long, env = "AMP_SERVER")]
     pub amp_server: String,
 
     #[clap(long, env = "AUTH_TOKEN")]
     pub auth_token: Option<String>,
+
+    /// The GitHub REST API endpoint, e.g. of a GitHub Enterprise Server.
+    #[clap(long, env = "AMP_GITHUB_ENDPOINT", default_value = GITHUB_ENDPOINT)]
+    pub github_endpoint: String,
+
+    /// The pool of GitHub tokens the SCM calls are spread across, separated by commas, `AUTH_TOKEN` if empty.
+    #[clap(long, env = "AMP_GITHUB_TOKENS", value_delimiter = ',')]
+    pub github_tokens: Vec<String>,
+
+    /// How long (in seconds) the repository search results are cached.
+    #[clap(long, env = "AMP_REPO_SEARCH_CACHE_TTL", default_value = "60")]
+    pub repo_search_cache_ttl: u64,
+
+    /// The playbook templates catalogue, either a TOML file or a directory of TOML files.
+    #[clap(long, env = "AMP_TEMPLATES")]
+    pub templates: Option<PathBuf>,
+
+    /// The directory where the local data, e.g. the playbook snapshots, is persisted.
+    #[clap(long, env = "AMP_DATA_DIR", default_value = "./data")]
+    pub data_dir: PathBuf,
+
+    /// The maximum number of files a playbook snapshot may contain.
+    #[clap(long, env = "AMP_SNAPSHOT_MAX_FILES", default_value = "500")]
+    pub snapshot_max_files: usize,
+
+    /// Stop the playbooks created through this API without any activity for this long (in seconds),
+    /// 0 to disable.
+    #[clap(long, env = "AMP_IDLE_TIMEOUT", default_value = "1800")]
+    pub idle_timeout: u64,
+
+    /// Delete the playbooks without any activity for this long (in seconds), 0 to keep them until
+    /// they are deleted, which is the default.
+    #[clap(long, env = "AMP_PLAYBOOK_TTL", default_value = "0")]
+    pub playbook_ttl: u64,
+
+    /// How often (in seconds) the idle playbooks, and the ones out of running time, are looked for.
+    #[clap(long, env = "AMP_REAPER_INTERVAL", default_value = "60")]
+    pub reaper_interval: u64,
+
+    /// The maximum number of playbooks per user, 0 for unlimited.
+    #[clap(long, env = "AMP_QUOTA_PLAYBOOKS", default_value = "10")]
+    pub quota_playbooks: u64,
+
+    /// The maximum size (in bytes) of the files written to the workspaces per user, 0 for unlimited.
+    #[clap(long, env = "AMP_QUOTA_WORKSPACE_BYTES", default_value = "52428800")]
+    pub quota_workspace_bytes: u64,
+
+    /// The maximum running time (in seconds) of the playbooks per user, the ones still running past it
+    /// are stopped, 0 for unlimited.
+    #[clap(long, env = "AMP_QUOTA_RUNNING_SECONDS", default_value = "36000")]
+    pub quota_running_seconds: u64,
+
+    /// How long (in seconds) an operation waits for the playbook to be running.
+    #[clap(long, env = "AMP_PROVISION_TIMEOUT", default_value = "600")]
+    pub provision_timeout: u64,
+
+    /// How long (in seconds) the responses are kept for replaying the requests with the same `Idempotency-Key`.
+    #[clap(long, env = "AMP_IDEMPOTENCY_TTL", default_value = "86400")]
+    pub idempotency_ttl: u64,
+
+    /// How long (in seconds) the readiness probe waits for each dependency.
+    #[clap(long, env = "AMP_READINESS_TIMEOUT", default_value = "3")]
+    pub readiness_timeout: u64,
+
+    /// How long (in seconds) the dependencies probed are reused by the next readiness probes,
+    /// about the period of the probe, so the dependencies are not called on every probe.
+    #[clap(long, env = "AMP_READINESS_CACHE_TTL", default_value = "10")]
+    pub readiness_cache_ttl: u64,
+
+    /// Where the trace spans are exported to: `none`, `otlp`, `stderr` (apart from the logs on stdout) or `file`.
+    #[clap(long, env = "AMP_TRACE_EXPORTER", value_enum, default_value = "none")]
+    pub trace_exporter: TraceExporter,
+
+    /// The OTLP/HTTP endpoint of the trace collector, used by the `otlp` exporter.
+    #[clap(long, env = "AMP_OTLP_ENDPOINT", default_value = "http://localhost:4318/v1/traces")]
+    pub otlp_endpoint: String,
+
+    /// The file the spans are appended to as JSON lines, used by the `file` exporter.
+    #[clap(long, env = "AMP_TRACE_FILE", default_value = "./traces.jsonl")]
+    pub trace_file: PathBuf,
+
+    /// The format of the logs: `pretty` for humans or `json` for the log collectors.
+    #[clap(long, env = "AMP_LOG_FORMAT", value_enum, default_value = "pretty")]
+    pub log_format: LogFormat,
+
+    /// How long (in seconds) the requests are still served once the shutdown is announced by the readiness probe,
+    /// for the load balancers to stop routing new traffic here before the server stops accepting it.
+    #[clap(long, env = "AMP_SHUTDOWN_GRACE_PERIOD", default_value = "10")]
+    pub shutdown_grace_period: u64,
+
+    /// How long (in seconds) the in-flight requests are given to finish on shutdown.
+    #[clap(long, env = "AMP_DRAIN_TIMEOUT", default_value = "30")]
+    pub drain_timeout: u64,
+
+    /// The origins allowed to call this API from the browsers, separated by commas, `*` for any origin.
+    #[clap(long, env = "AMP_CORS_ALLOWED_ORIGINS", value_delimiter = ',')]
+    pub cors_allowed_origins: Vec<String>,
+
+    /// Whether the responses are compressed with gzip, br or zstd, the event streams never are.
+    #[clap(long, env = "AMP_COMPRESSION", default_value = "true", action = clap::ArgAction::Set)]
+    pub compression: bool,
+
+    /// The maximum size (in bytes) of the request bodies.
+    #[clap(long, env = "AMP_MAX_BODY_SIZE", default_value = "1048576")]
+    pub max_body_size: usize,
+
+    /// The maximum size (in bytes) of the request bodies carrying the file contents.
+    #[clap(long, env = "AMP_MAX_UPLOAD_SIZE", default_value = "10485760")]
+    pub max_upload_size: usize,
+
+    /// How long (in seconds) a request may take before it's answered with 504, the streams excluded.
+    #[clap(long, env = "AMP_REQUEST_TIMEOUT", default_value = "30")]
+    pub request_timeout: u64,
+
+    /// How long (in seconds) a request provisioning the playbooks may take before it's answered with 504.
+    #[clap(long, env = "AMP_LONG_REQUEST_TIMEOUT", default_value = "660")]
+    pub long_request_timeout: u64,
+
+    /// The requests per minute each user, or client IP for the anonymous ones, may make, 0 for unlimited.
+    #[clap(long, env = "AMP_RATE_LIMIT", default_value = "300")]
+    pub rate_limit: u32,
+
+    /// The expensive requests per minute, i.e. creating playbooks and listing the recursive trees, 0 for unlimited.
+    #[clap(long, env = "AMP_EXPENSIVE_RATE_LIMIT", default_value = "10")]
+    pub expensive_rate_limit: u32,
+
+    /// The proxies (IP addresses or CIDR ranges) trusted to tell the user with `X-User-Id`, e.g. the
+    /// Playground gateway, and the client IP with `X-Forwarded-For`. The headers are ignored from the
+    /// other clients, which are told apart by IP.
+    #[clap(long, env = "AMP_TRUSTED_PROXIES", value_delimiter = ',', value_parser = parse_network)]
+    pub trusted_proxies: Vec<IpNet>,
+
+    /// How long (in seconds) the commit a branch points at is trusted before revalidating it with GitHub.
+    #[clap(long, env = "AMP_SCM_CACHE_TTL", default_value = "60")]
+    pub scm_cache_ttl: u64,
+
+    /// The maximum size (in bytes) of the files, folders and trees cached from GitHub.
+    #[clap(long, env = "AMP_SCM_CACHE_SIZE", default_value = "67108864")]
+    pub scm_cache_size: u64,
+
+    /// How long (in seconds) a call to the Amphitheatre server may take before it's given up, short enough
+    /// for the retries to fit in `AMP_REQUEST_TIMEOUT`, see [`Config::validate`].
+    #[clap(long, env = "AMP_UPSTREAM_TIMEOUT", default_value = "8")]
+    pub upstream_timeout: u64,
+
+    /// How many times the idempotent calls to the Amphitheatre server are retried while it's failing.
+    #[clap(long, env = "AMP_UPSTREAM_RETRIES", default_value = "2")]
+    pub upstream_retries: u32,
+
+    /// How long (in milliseconds) to wait before the first retry, doubled for each of the next ones.
+    #[clap(long, env = "AMP_UPSTREAM_BACKOFF", default_value = "200")]
+    pub upstream_backoff: u64,
+
+    /// How many consecutive failures of the Amphitheatre server open the circuit breaker, 0 to disable it.
+    #[clap(long, env = "AMP_BREAKER_THRESHOLD", default_value = "5")]
+    pub breaker_threshold: u32,
+
+    /// How long (in seconds) the open circuit breaker fails the calls fast, before trying the server again.
+    #[clap(long, env = "AMP_BREAKER_COOLDOWN", default_value = "30")]
+    pub breaker_cooldown: u64,
+}
+
+impl Config {
+    /// Check the options which only make sense together, e.g. the upstream calls must be given up,
+    /// with all their retries, before the request itself times out.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let attempts = u64::from(self.upstream_retries) + 1;
+        let backoff: u64 = (0..self.upstream_retries)
+            .map(|retry| self.upstream_backoff.saturating_mul(1 << retry.min(16)).min(MAX_BACKOFF.as_millis() as u64))
+            .sum();
+        let worst = self.upstream_timeout.saturating_mul(attempts * 1000).saturating_add(backoff);
+        if worst >= self.request_timeout.saturating_mul(1000) {
+            anyhow::bail!(
+                "AMP_UPSTREAM_TIMEOUT ({}s) with {} retries takes up to {}ms, longer than AMP_REQUEST_TIMEOUT ({}s)",
+                self.upstream_timeout,
+                self.upstream_retries,
+                worst,
+                self.request_timeout
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// The formats of the logs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+/// The exporters of the trace spans.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum TraceExporter {
+    None,
+    Otlp,
+    /// Written to stderr, so the spans are not mixed with the logs written to stdout.
+    #[value(alias = "stdout")]
+    Stderr,
+    File,
+}
+
+/// Parse a CIDR range, or a single IP address as the range of just itself.
+fn parse_network(value: &str) -> Result<IpNet, String> {
+    let value = value.trim();
+    value
+        .parse::<IpNet>()
+        .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("{:?} is neither an IP address nor a CIDR range", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn config(args: &[&str]) -> Config {
+        Config::parse_from(["playground", "--port", "0", "--amp-server", "http://127.0.0.1:9"].iter().chain(args))
+    }
+
+    #[test]
+    fn fits_the_upstream_retries_in_the_request_timeout() {
+        assert!(config(&[]).validate().is_ok());
+        assert!(config(&["--upstream-timeout", "30"]).validate().is_err());
+        assert!(config(&["--upstream-timeout", "10"]).validate().is_err());
+        assert!(config(&["--upstream-timeout", "10", "--upstream-retries", "0"]).validate().is_ok());
+        assert!(config(&["--upstream-timeout", "30", "--request-timeout", "120"]).validate().is_ok());
+    }
 }
@@ -12,12 +12,29 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::activity::ActivityTracker;
 use crate::config::Config;
+use crate::events::EventBus;
+use crate::github::TokenPool;
+use crate::middleware::idempotency::IdempotencyStore;
+use crate::middleware::rate_limit::RateLimiter;
+use crate::operations::OperationStore;
+use crate::overlay::OverlayStore;
+use crate::quota::QuotaTracker;
+use crate::responses::repo::RepositorySearchResponse;
+use crate::scm_cache::ScmCache;
+use crate::services::ReadinessCache;
+use crate::shutdown::Shutdown;
+use crate::snapshots::SnapshotStore;
+use crate::templates::TemplateRegistry;
+use crate::upstream::Upstream;
 use amp_client::client::Client;
-use amp_common::scm::client::Client as ScmClient;
-use amp_common::scm::driver::github;
-use amp_common::scm::driver::github::constants::GITHUB_ENDPOINT;
+use moka::future::Cache;
 use std::sync::Arc;
+use std::time::Duration;
+
+/// The maximum number of cached repository search results.
+const REPO_SEARCH_CACHE_CAPACITY: u64 = 1_000;
 
 /// The core type through which handler functions can access common API state.
 ///
@@ -30,8 +47,23 @@ use std::sync::Arc;
 #[derive(Clone)]
 pub struct Context {
     pub config: Config,
-    pub client: Arc<Client>,
-    pub github_client: Arc<ScmClient>,
+    pub upstream: Arc<Upstream>,
+    pub github: Arc<TokenPool>,
+    pub http_client: reqwest::Client,
+    pub repo_search_cache: Cache<String, RepositorySearchResponse>,
+    pub scm_cache: Arc<ScmCache>,
+    pub templates: Arc<TemplateRegistry>,
+    pub overlays: Arc<OverlayStore>,
+    pub snapshots: Arc<SnapshotStore>,
+    pub activity: Arc<ActivityTracker>,
+    pub quotas: Arc<QuotaTracker>,
+    pub events: Arc<EventBus>,
+    pub operations: Arc<OperationStore>,
+    pub idempotency: IdempotencyStore,
+    pub rate_limiter: RateLimiter,
+    pub shutdown: Arc<Shutdown>,
+    /// The dependencies last probed by the readiness probe, and when.
+    pub readiness: Arc<ReadinessCache>,
 }
 
 impl Context {
@@ -39,10 +71,65 @@ impl Context {
         // Create amphitheatre client
         let client = Arc::new(Client::new(&config.amp_server, config.auth_token.clone()));
 
-        // Create SCM client with GitHub driver
-        let github_driver = github::new(GITHUB_ENDPOINT, config.auth_token.clone())?;
-        let github_client = Arc::new(ScmClient::new(github_driver));
+        // The calls to the amphitheatre server go through the timeouts, retries and circuit breaker,
+        // and with the client from `upstream.client()` to carry the trace context
+        let upstream = Arc::new(Upstream::new(&config, client));
+
+        // Create a plain HTTP client for the GitHub APIs not covered by the SCM client
+        let http_client = reqwest::Client::builder().user_agent(env!("CARGO_PKG_NAME")).build()?;
+
+        // Create an SCM client with GitHub driver for each of the pooled tokens
+        let github = Arc::new(TokenPool::new(&config, http_client.clone())?);
+
+        // Repository search results are cached briefly to save the GitHub rate limit
+        let repo_search_cache = Cache::builder()
+            .max_capacity(REPO_SEARCH_CACHE_CAPACITY)
+            .time_to_live(Duration::from_secs(config.repo_search_cache_ttl))
+            .build();
+
+        // The files, folders and trees read from GitHub are cached by commit
+        let scm_cache = Arc::new(ScmCache::new(&config));
+
+        // Load the playbook templates catalogue if configured
+        let templates = match &config.templates {
+            Some(path) => TemplateRegistry::load(path)?,
+            None => TemplateRegistry::default(),
+        };
+        let templates = Arc::new(templates);
+
+        // The playbook snapshots are persisted in the local data directory
+        let snapshots = Arc::new(SnapshotStore::new(&config.data_dir));
+
+        // The changes made on the workspaces are kept for replaying them, e.g. when forking
+        let overlays = Arc::new(OverlayStore::open(&config.data_dir)?);
+
+        // The usage of each user is tracked locally against the configured limits, and persisted
+        let quotas = Arc::new(QuotaTracker::open(&config)?);
+
+        // The responses of the requests with an Idempotency-Key are kept for replaying the retries
+        let idempotency = IdempotencyStore::new(Duration::from_secs(config.idempotency_ttl));
+
+        // Each user or client IP is given its own budgets of requests per minute
+        let rate_limiter = RateLimiter::new(config.rate_limit, config.expensive_rate_limit);
 
-        Ok(Context { config, client, github_client })
+        Ok(Context {
+            config,
+            upstream,
+            github,
+            http_client,
+            repo_search_cache,
+            scm_cache,
+            templates,
+            overlays,
+            snapshots,
+            activity: Arc::new(ActivityTracker::default()),
+            quotas,
+            events: Arc::new(EventBus::default()),
+            operations: Arc::new(OperationStore::default()),
+            idempotency,
+            rate_limiter,
+            shutdown: Arc::new(Shutdown::default()),
+            readiness: Arc::default(),
+        })
     }
 }
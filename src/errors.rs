@@ -12,17 +12,39 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use amp_common::http::HTTPError;
 use amp_common::scm::errors::SCMError;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::http::header::{CONTENT_TYPE, RETRY_AFTER};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::Json;
-use serde_json::json;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use thiserror::Error;
 use tracing::error;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::middleware::request_id;
+use crate::quota::Resource;
+use crate::responses::playbook::ValidationError;
 
 pub type Result<T, E = ApiError> = std::result::Result<T, E>;
 
+/// The base of the `type` URIs of the problems, followed by the error code.
+pub const PROBLEM_TYPE_BASE: &str = "https://amphitheatre.app/problems/";
+
+/// The media type of the error responses, see RFC 7807.
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// How long (in seconds) the clients are advised to wait when the upstream is rate limited,
+/// as the upstream errors don't carry their own `Retry-After`.
+pub const UPSTREAM_RETRY_AFTER: u64 = 60;
+
 #[derive(Debug, Error)]
 pub enum ApiError {
     #[error("Internal Server Error")]
@@ -31,26 +53,44 @@ pub enum ApiError {
     #[error("Not Found")]
     NotFound,
 
+    #[error("Method Not Allowed")]
+    MethodNotAllowed,
+
+    #[error("Invalid Request: {1}")]
+    InvalidRequest(StatusCode, String),
+
+    #[error("Payload Too Large: {0}")]
+    PayloadTooLarge(String),
+
+    #[error("Request Timeout: no response in {0}s")]
+    RequestTimeout(u64),
+
     #[error("Not Found Playbook: {0}")]
     NotFoundPlaybook(HTTPError),
 
     #[error("Failed to create playbook: {0}")]
     FailedToCreatePlaybook(HTTPError),
 
+    #[error("Failed to update playbook: {0}")]
+    FailedToUpdatePlaybook(HTTPError),
+
     #[error("Failed to delete playbook: {0}")]
     FailedToDeletePlaybook(HTTPError),
 
     #[error("Failed to start playbook: {0}")]
     FailedToStartPlaybook(HTTPError),
 
+    #[error("Failed to stop playbook: {0}")]
+    FailedToStopPlaybook(HTTPError),
+
     #[error("Not Found Content: {0}")]
-    NotFoundContent(String),
+    NotFoundContent(SCMError),
 
     #[error("InvalidRepoAddress: {0}")]
     InvalidRepoAddress(#[source] url::ParseError),
 
     #[error("Not Found Folder: {0}")]
-    NotFoundFolder(String),
+    NotFoundFolder(SCMError),
 
     #[error("Failed to synchronize: {0}")]
     FailedToSynchronize(HTTPError),
@@ -63,27 +103,424 @@ pub enum ApiError {
 
     #[error("Bad Playbook Request: {0}")]
     BadPlaybookRequest(String),
+
+    #[error("Bad Search Request: {0}")]
+    BadSearchRequest(String),
+
+    #[error("Failed to search repositories: {0}")]
+    FailedToSearchRepos(SCMError),
+
+    #[error("Failed to read repository: {0}")]
+    FailedToReadRepo(SCMError),
+
+    #[error("Invalid Playbook: {} error(s) found", .0.len())]
+    InvalidPlaybook(Vec<ValidationError>),
+
+    #[error("Not Found Template: {0}")]
+    NotFoundTemplate(String),
+
+    #[error("Not Found Snapshot: {0}")]
+    NotFoundSnapshot(String),
+
+    #[error("Failed to snapshot: {0}")]
+    FailedToSnapshot(String),
+
+    #[error("Failed to provision playbook: {0}")]
+    FailedToProvision(String),
+
+    #[error("Not Found Operation: {0}")]
+    NotFoundOperation(Uuid),
+
+    #[error("The Idempotency-Key was already used with a different request body")]
+    IdempotencyKeyReused,
+
+    #[error("A request with the same Idempotency-Key is still being processed")]
+    IdempotencyKeyInFlight,
+
+    #[error("Quota Exceeded: {used} {resource} of {limit}")]
+    QuotaExceeded { resource: Resource, used: u64, limit: u64 },
+
+    #[error("Rate Limited: {limit} {budget} requests per minute, retry in {retry_after}s")]
+    RateLimited { budget: &'static str, limit: u32, retry_after: u64 },
+
+    #[error("GitHub Rate Limited: resets at {reset}")]
+    GitHubRateLimited { reset: u64 },
+
+    #[error("Upstream Unavailable: the {service} server keeps failing, retry in {retry_after}s")]
+    UpstreamUnavailable { service: &'static str, retry_after: u64 },
+}
+
+/// The stable machine-readable codes of the errors, the clients can branch on them
+/// while the details are free to change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InternalError,
+    NotFound,
+    MethodNotAllowed,
+    InvalidRequest,
+    PayloadTooLarge,
+    RequestTimeout,
+    PlaybookNotFound,
+    PlaybookCreateFailed,
+    PlaybookUpdateFailed,
+    PlaybookDeleteFailed,
+    PlaybookStartFailed,
+    PlaybookStopFailed,
+    ContentNotFound,
+    InvalidRepoAddress,
+    FolderNotFound,
+    SyncFailed,
+    BadPlaybook,
+    RepoNotFound,
+    BadPlaybookRequest,
+    BadSearchRequest,
+    RepoSearchFailed,
+    RepoReadFailed,
+    InvalidPlaybook,
+    TemplateNotFound,
+    SnapshotNotFound,
+    SnapshotFailed,
+    ProvisionFailed,
+    OperationNotFound,
+    IdempotencyKeyReused,
+    IdempotencyKeyInFlight,
+    QuotaExceeded,
+    RateLimited,
+    GitHubRateLimited,
+    UpstreamUnavailable,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::InternalError => "internal_error",
+            Self::NotFound => "not_found",
+            Self::MethodNotAllowed => "method_not_allowed",
+            Self::InvalidRequest => "invalid_request",
+            Self::PayloadTooLarge => "payload_too_large",
+            Self::RequestTimeout => "request_timeout",
+            Self::PlaybookNotFound => "playbook_not_found",
+            Self::PlaybookCreateFailed => "playbook_create_failed",
+            Self::PlaybookUpdateFailed => "playbook_update_failed",
+            Self::PlaybookDeleteFailed => "playbook_delete_failed",
+            Self::PlaybookStartFailed => "playbook_start_failed",
+            Self::PlaybookStopFailed => "playbook_stop_failed",
+            Self::ContentNotFound => "content_not_found",
+            Self::InvalidRepoAddress => "invalid_repo_address",
+            Self::FolderNotFound => "folder_not_found",
+            Self::SyncFailed => "sync_failed",
+            Self::BadPlaybook => "bad_playbook",
+            Self::RepoNotFound => "repo_not_found",
+            Self::BadPlaybookRequest => "bad_playbook_request",
+            Self::BadSearchRequest => "bad_search_request",
+            Self::RepoSearchFailed => "repo_search_failed",
+            Self::RepoReadFailed => "repo_read_failed",
+            Self::InvalidPlaybook => "invalid_playbook",
+            Self::TemplateNotFound => "template_not_found",
+            Self::SnapshotNotFound => "snapshot_not_found",
+            Self::SnapshotFailed => "snapshot_failed",
+            Self::ProvisionFailed => "provision_failed",
+            Self::OperationNotFound => "operation_not_found",
+            Self::IdempotencyKeyReused => "idempotency_key_reused",
+            Self::IdempotencyKeyInFlight => "idempotency_key_in_flight",
+            Self::QuotaExceeded => "quota_exceeded",
+            Self::RateLimited => "rate_limited",
+            Self::GitHubRateLimited => "github_rate_limited",
+            Self::UpstreamUnavailable => "upstream_unavailable",
+        }
+    }
+
+    /// A short summary of the problem, which doesn't change from occurrence to occurrence.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::InternalError => "Internal server error",
+            Self::NotFound => "Not found",
+            Self::MethodNotAllowed => "Method not allowed",
+            Self::InvalidRequest => "Invalid request",
+            Self::PayloadTooLarge => "Payload too large",
+            Self::RequestTimeout => "Request timeout",
+            Self::PlaybookNotFound => "Playbook not found",
+            Self::PlaybookCreateFailed => "Failed to create the playbook",
+            Self::PlaybookUpdateFailed => "Failed to update the playbook",
+            Self::PlaybookDeleteFailed => "Failed to delete the playbook",
+            Self::PlaybookStartFailed => "Failed to start the playbook",
+            Self::PlaybookStopFailed => "Failed to stop the playbook",
+            Self::ContentNotFound => "Content not found",
+            Self::InvalidRepoAddress => "Invalid repository address",
+            Self::FolderNotFound => "Folder not found",
+            Self::SyncFailed => "Failed to synchronize the workspace",
+            Self::BadPlaybook => "Bad playbook",
+            Self::RepoNotFound => "Repository not found",
+            Self::BadPlaybookRequest => "Bad playbook request",
+            Self::BadSearchRequest => "Bad search request",
+            Self::RepoSearchFailed => "Failed to search the repositories",
+            Self::RepoReadFailed => "Failed to read the repository",
+            Self::InvalidPlaybook => "Invalid playbook",
+            Self::TemplateNotFound => "Template not found",
+            Self::SnapshotNotFound => "Snapshot not found",
+            Self::SnapshotFailed => "Failed to snapshot the playbook",
+            Self::ProvisionFailed => "Failed to provision the playbook",
+            Self::OperationNotFound => "Operation not found",
+            Self::IdempotencyKeyReused => "Idempotency key reused",
+            Self::IdempotencyKeyInFlight => "Idempotency key in flight",
+            Self::QuotaExceeded => "Quota exceeded",
+            Self::RateLimited => "Rate limit exceeded",
+            Self::GitHubRateLimited => "GitHub rate limit exceeded",
+            Self::UpstreamUnavailable => "Upstream unavailable",
+        }
+    }
+}
+
+/// An error response in the `application/problem+json` format of RFC 7807.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Problem {
+    /// A URI identifying the problem type.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: String,
+    pub status: u16,
+    /// The explanation specific to this occurrence of the problem.
+    pub detail: String,
+    pub code: ErrorCode,
+    /// The path of the request the problem occurred on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// What the problem is about, e.g. the playbook and the file path.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[schema(value_type = Object)]
+    pub context: BTreeMap<String, Value>,
+    /// The offending fields of an invalid playbook.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ValidationError>,
+}
+
+impl ApiError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::InternalServerError => ErrorCode::InternalError,
+            Self::NotFound => ErrorCode::NotFound,
+            Self::MethodNotAllowed => ErrorCode::MethodNotAllowed,
+            Self::InvalidRequest(..) => ErrorCode::InvalidRequest,
+            Self::PayloadTooLarge(_) => ErrorCode::PayloadTooLarge,
+            Self::RequestTimeout(_) => ErrorCode::RequestTimeout,
+            Self::NotFoundPlaybook(_) => ErrorCode::PlaybookNotFound,
+            Self::FailedToCreatePlaybook(_) => ErrorCode::PlaybookCreateFailed,
+            Self::FailedToUpdatePlaybook(_) => ErrorCode::PlaybookUpdateFailed,
+            Self::FailedToDeletePlaybook(_) => ErrorCode::PlaybookDeleteFailed,
+            Self::FailedToStartPlaybook(_) => ErrorCode::PlaybookStartFailed,
+            Self::FailedToStopPlaybook(_) => ErrorCode::PlaybookStopFailed,
+            Self::NotFoundContent(_) => ErrorCode::ContentNotFound,
+            Self::InvalidRepoAddress(_) => ErrorCode::InvalidRepoAddress,
+            Self::NotFoundFolder(_) => ErrorCode::FolderNotFound,
+            Self::FailedToSynchronize(_) => ErrorCode::SyncFailed,
+            Self::BadPlaybook(_) => ErrorCode::BadPlaybook,
+            Self::NotFoundRepo(_) => ErrorCode::RepoNotFound,
+            Self::BadPlaybookRequest(_) => ErrorCode::BadPlaybookRequest,
+            Self::BadSearchRequest(_) => ErrorCode::BadSearchRequest,
+            Self::FailedToSearchRepos(_) => ErrorCode::RepoSearchFailed,
+            Self::FailedToReadRepo(_) => ErrorCode::RepoReadFailed,
+            Self::InvalidPlaybook(_) => ErrorCode::InvalidPlaybook,
+            Self::NotFoundTemplate(_) => ErrorCode::TemplateNotFound,
+            Self::NotFoundSnapshot(_) => ErrorCode::SnapshotNotFound,
+            Self::FailedToSnapshot(_) => ErrorCode::SnapshotFailed,
+            Self::FailedToProvision(_) => ErrorCode::ProvisionFailed,
+            Self::NotFoundOperation(_) => ErrorCode::OperationNotFound,
+            Self::IdempotencyKeyReused => ErrorCode::IdempotencyKeyReused,
+            Self::IdempotencyKeyInFlight => ErrorCode::IdempotencyKeyInFlight,
+            Self::QuotaExceeded { .. } => ErrorCode::QuotaExceeded,
+            Self::RateLimited { .. } => ErrorCode::RateLimited,
+            Self::GitHubRateLimited { .. } => ErrorCode::GitHubRateLimited,
+            Self::UpstreamUnavailable { .. } => ErrorCode::UpstreamUnavailable,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            Self::InvalidRequest(status, _) => *status,
+            Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::RequestTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            // Tell what the upstream said, rather than blaming either side blindly.
+            Self::NotFoundPlaybook(e)
+            | Self::FailedToCreatePlaybook(e)
+            | Self::FailedToUpdatePlaybook(e)
+            | Self::FailedToDeletePlaybook(e)
+            | Self::FailedToStartPlaybook(e)
+            | Self::FailedToStopPlaybook(e)
+            | Self::FailedToSynchronize(e) => upstream_status(e),
+            Self::InvalidRepoAddress(_) => StatusCode::BAD_REQUEST,
+            Self::BadPlaybook(_) => StatusCode::BAD_REQUEST,
+            Self::NotFoundRepo(SCMError::ClientError(e))
+            | Self::NotFoundContent(SCMError::ClientError(e))
+            | Self::NotFoundFolder(SCMError::ClientError(e))
+            | Self::FailedToSearchRepos(SCMError::ClientError(e))
+            | Self::FailedToReadRepo(SCMError::ClientError(e)) => upstream_status(e),
+            Self::NotFoundRepo(_)
+            | Self::NotFoundContent(_)
+            | Self::NotFoundFolder(_)
+            | Self::FailedToSearchRepos(_)
+            | Self::FailedToReadRepo(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::BadPlaybookRequest(_) => StatusCode::BAD_REQUEST,
+            Self::BadSearchRequest(_) => StatusCode::BAD_REQUEST,
+            Self::InvalidPlaybook(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::NotFoundTemplate(_) => StatusCode::NOT_FOUND,
+            Self::NotFoundSnapshot(_) => StatusCode::NOT_FOUND,
+            Self::FailedToSnapshot(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::FailedToProvision(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFoundOperation(_) => StatusCode::NOT_FOUND,
+            Self::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::IdempotencyKeyInFlight => StatusCode::CONFLICT,
+            // Too many playbooks can be solved by deleting some, the other limits can't be retried.
+            Self::QuotaExceeded { resource: Resource::Playbooks, .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::QuotaExceeded { .. } => StatusCode::FORBIDDEN,
+            Self::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::GitHubRateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::UpstreamUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// How long (in seconds) the client should wait before retrying, if it or the upstream is rate limited,
+    /// or the upstream is unavailable.
+    pub fn retry_after(&self) -> Option<u64> {
+        let upstream = match self {
+            Self::RateLimited { retry_after, .. } | Self::UpstreamUnavailable { retry_after, .. } => {
+                return Some(*retry_after)
+            }
+            Self::GitHubRateLimited { reset } => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+                return Some(reset.saturating_sub(now).max(1));
+            }
+            Self::NotFoundPlaybook(e)
+            | Self::FailedToCreatePlaybook(e)
+            | Self::FailedToUpdatePlaybook(e)
+            | Self::FailedToDeletePlaybook(e)
+            | Self::FailedToStartPlaybook(e)
+            | Self::FailedToStopPlaybook(e)
+            | Self::FailedToSynchronize(e)
+            | Self::NotFoundRepo(SCMError::ClientError(e))
+            | Self::NotFoundContent(SCMError::ClientError(e))
+            | Self::NotFoundFolder(SCMError::ClientError(e))
+            | Self::FailedToSearchRepos(SCMError::ClientError(e))
+            | Self::FailedToReadRepo(SCMError::ClientError(e)) => e,
+            _ => return None,
+        };
+
+        (upstream_status(upstream) == StatusCode::TOO_MANY_REQUESTS).then_some(UPSTREAM_RETRY_AFTER)
+    }
+
+    /// What the error itself knows about the resources involved.
+    fn context(&self) -> BTreeMap<String, Value> {
+        let context = match self {
+            Self::NotFoundTemplate(id) => json!({ "template": id }),
+            Self::NotFoundSnapshot(slug) => json!({ "snapshot": slug }),
+            Self::NotFoundOperation(id) => json!({ "operation": id }),
+            Self::QuotaExceeded { resource, used, limit } => {
+                json!({ "resource": resource.to_string(), "used": used, "limit": limit })
+            }
+            Self::RateLimited { budget, limit, .. } => json!({ "budget": budget, "limit": limit }),
+            Self::GitHubRateLimited { reset } => json!({ "reset": reset }),
+            Self::UpstreamUnavailable { service, .. } => json!({ "service": service }),
+            _ => json!({}),
+        };
+
+        serde_json::from_value(context).unwrap_or_default()
+    }
+
+    /// Describe the error as a problem, in the context of the request being handled.
+    pub fn problem(&self) -> Problem {
+        let code = self.code();
+        let scope = request_id::current();
+
+        // The route parameters tell which playbook and path the error is about.
+        let mut context = BTreeMap::new();
+        for (name, value) in scope.iter().flat_map(|s| s.params.iter()) {
+            let name = if name == "id" { "playbook" } else { name.as_str() };
+            context.insert(name.to_string(), Value::String(value.clone()));
+        }
+        context.extend(self.context());
+
+        Problem {
+            kind: format!("{}{}", PROBLEM_TYPE_BASE, code.as_str()),
+            title: code.title().to_string(),
+            status: self.status().as_u16(),
+            detail: self.to_string(),
+            code,
+            instance: scope.as_ref().map(|s| s.path.clone()),
+            request_id: scope.map(|s| s.id),
+            context,
+            errors: match self {
+                Self::InvalidPlaybook(errors) => errors.clone(),
+                _ => vec![],
+            },
+        }
+    }
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> axum::response::Response {
-        let (status, message) = match self {
-            Self::InternalServerError => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            Self::NotFound => (StatusCode::NOT_FOUND, self.to_string()),
-            Self::NotFoundPlaybook(e) => (StatusCode::NOT_FOUND, e.to_string()),
-            Self::FailedToCreatePlaybook(e) => (StatusCode::BAD_REQUEST, e.to_string()),
-            Self::FailedToDeletePlaybook(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
-            Self::FailedToStartPlaybook(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
-            Self::NotFoundContent(e) => (StatusCode::NOT_FOUND, e.to_string()),
-            Self::InvalidRepoAddress(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
-            Self::NotFoundFolder(e) => (StatusCode::NOT_FOUND, e.to_string()),
-            Self::FailedToSynchronize(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
-            Self::BadPlaybook(e) => (StatusCode::BAD_REQUEST, e.to_string()),
-            Self::NotFoundRepo(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
-            Self::BadPlaybookRequest(e) => (StatusCode::BAD_REQUEST, e.to_string()),
-        };
+        let status = self.status();
+        let problem = self.problem();
+        metrics::counter!("playground_api_errors_total", "code" => problem.code.as_str()).increment(1);
+        error!("{} - {} - {}", status, problem.code.as_str(), problem.detail);
+
+        let mut response = (status, [(CONTENT_TYPE, PROBLEM_CONTENT_TYPE)], Json(problem)).into_response();
+        if let Some(seconds) = self.retry_after() {
+            response.headers_mut().insert(RETRY_AFTER, seconds.into());
+        }
+
+        response
+    }
+}
+
+impl ApiError {
+    /// Describe a rejection of the extractors, keeping its status and message.
+    fn rejected(status: StatusCode, message: String) -> Self {
+        match status {
+            StatusCode::PAYLOAD_TOO_LARGE => Self::PayloadTooLarge(message),
+            status => Self::InvalidRequest(status, message),
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::rejected(rejection.status(), rejection.body_text())
+    }
+}
 
-        error!("{} - {}", status, message);
-        (status, Json(json!({ "message": message }))).into_response()
+/// Map the error of an upstream call to the status of our response: the client errors the caller
+/// can act on are passed through, e.g. a private repository, while a failing or misbehaving upstream
+/// is a bad gateway.
+fn upstream_status(e: &HTTPError) -> StatusCode {
+    match e {
+        HTTPError::BadRequest { .. } => StatusCode::BAD_REQUEST,
+        HTTPError::NotFound(_) => StatusCode::NOT_FOUND,
+        HTTPError::Unauthorized => StatusCode::UNAUTHORIZED,
+        HTTPError::PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
+        HTTPError::GatewayTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        HTTPError::Transport(code, _) => match StatusCode::from_u16(*code) {
+            Ok(status) if status.is_client_error() => status,
+            _ => StatusCode::BAD_GATEWAY,
+        },
+        HTTPError::MethodNotAllowed | HTTPError::NotImplemented(_) | HTTPError::Deserialization(_) => {
+            StatusCode::BAD_GATEWAY
+        }
     }
 }
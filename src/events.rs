@@ -0,0 +1,284 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// How many events are kept per playbook for the reconnecting clients.
+const HISTORY_SIZE: usize = 100;
+
+/// How many events can be buffered for a slow subscriber before it lags.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// The kinds of playbook lifecycle events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+    Created,
+    Building,
+    Running,
+    Failed,
+    Stopped,
+    Deleted,
+    FileSynced,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Created => "created",
+            EventKind::Building => "building",
+            EventKind::Running => "running",
+            EventKind::Failed => "failed",
+            EventKind::Stopped => "stopped",
+            EventKind::Deleted => "deleted",
+            EventKind::FileSynced => "file-synced",
+        }
+    }
+
+    /// Whether the event is a lifecycle state, rather than a one-off notification.
+    fn is_state(&self) -> bool {
+        !matches!(self, EventKind::FileSynced)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct PlaybookEvent {
+    /// The sequence number of the event, used as the SSE event id.
+    pub seq: u64,
+    /// The id of the playbook.
+    pub playbook: Uuid,
+    pub kind: EventKind,
+    /// Details of the event, e.g. the error message or the synced file path.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// When the event happened, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// Publishes the playbook lifecycle events to the subscribers, keeping a short history
+/// per playbook so the reconnecting clients can catch up with what they missed.
+#[derive(Debug)]
+pub struct EventBus {
+    sender: broadcast::Sender<PlaybookEvent>,
+    /// Tells the sequence numbers of this process apart from the ones before a restart,
+    /// which start over from 1.
+    epoch: u32,
+    seq: AtomicU64,
+    history: RwLock<HashMap<Uuid, VecDeque<PlaybookEvent>>>,
+    /// The number of clients watching each playbook, and whether it's being polled for them.
+    watchers: Mutex<HashMap<Uuid, Watchers>>,
+}
+
+#[derive(Debug, Default)]
+struct Watchers {
+    count: usize,
+    polled: bool,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            epoch: rand::random(),
+            seq: AtomicU64::new(0),
+            history: RwLock::new(HashMap::new()),
+            watchers: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl EventBus {
+    /// The id of the event for the clients, e.g. the SSE event id, valid across the restarts.
+    pub fn event_id(&self, event: &PlaybookEvent) -> String {
+        format!("{:08x}-{}", self.epoch, event.seq)
+    }
+
+    /// Returns the sequence number to resume after, from the id of the last event a client received.
+    /// All the events are replayed to the clients of a previous process, `None` if the id is not ours.
+    pub fn resume_after(&self, last_event_id: &str) -> Option<u64> {
+        let (epoch, seq) = last_event_id.split_once('-')?;
+        let (epoch, seq) = (u32::from_str_radix(epoch, 16).ok()?, seq.parse::<u64>().ok()?);
+
+        match epoch == self.epoch && seq <= self.seq.load(Ordering::SeqCst) {
+            true => Some(seq),
+            false => Some(0),
+        }
+    }
+
+    /// Count a new watcher of the playbook, returns whether the playbook is to be polled for it,
+    /// i.e. nobody is polling it yet.
+    pub fn watch(&self, playbook: Uuid) -> bool {
+        let mut watchers = self.watchers();
+        let watchers = watchers.entry(playbook).or_default();
+        watchers.count += 1;
+
+        !std::mem::replace(&mut watchers.polled, true)
+    }
+
+    /// Forget a watcher of the playbook once its client is gone.
+    pub fn unwatch(&self, playbook: Uuid) {
+        if let Some(watchers) = self.watchers().get_mut(&playbook) {
+            watchers.count = watchers.count.saturating_sub(1);
+        }
+    }
+
+    /// Whether the playbook is still to be polled, stops polling it once nobody watches it anymore.
+    pub fn keep_polling(&self, playbook: Uuid) -> bool {
+        let mut watchers = self.watchers();
+        match watchers.get(&playbook) {
+            Some(w) if w.count > 0 => true,
+            _ => {
+                watchers.remove(&playbook);
+                false
+            }
+        }
+    }
+
+    /// Publish an event of the playbook.
+    pub fn publish(&self, playbook: Uuid, kind: EventKind, message: Option<String>) {
+        let mut history = self.history.write().unwrap();
+        self.record(&mut history, playbook, kind, message);
+    }
+
+    /// Publish a lifecycle state of the playbook, unless it's already in that state.
+    pub fn transition(&self, playbook: Uuid, kind: EventKind, message: Option<String>) {
+        let mut history = self.history.write().unwrap();
+        if Self::state_of(&history, playbook) != Some(kind) {
+            self.record(&mut history, playbook, kind, message);
+        }
+    }
+
+    /// Returns the latest lifecycle state of the playbook.
+    pub fn state(&self, playbook: Uuid) -> Option<EventKind> {
+        Self::state_of(&self.history.read().unwrap(), playbook)
+    }
+
+    /// Returns the sequence number of the latest event, of any playbook.
+    pub fn last_seq(&self) -> u64 {
+        self.seq.load(Ordering::SeqCst)
+    }
+
+    /// Returns the events of the playbook published after the given sequence number.
+    pub fn since(&self, playbook: Uuid, seq: u64) -> Vec<PlaybookEvent> {
+        let history = self.history.read().unwrap();
+        history
+            .get(&playbook)
+            .map(|events| events.iter().filter(|e| e.seq > seq).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PlaybookEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Tell the watchers the playbook is deleted, which ends their streams, and forget its events.
+    pub fn forget(&self, playbook: Uuid) {
+        let mut history = self.history.write().unwrap();
+        self.record(&mut history, playbook, EventKind::Deleted, None);
+        history.remove(&playbook);
+    }
+
+    fn watchers(&self) -> MutexGuard<'_, HashMap<Uuid, Watchers>> {
+        self.watchers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn record(
+        &self,
+        history: &mut HashMap<Uuid, VecDeque<PlaybookEvent>>,
+        playbook: Uuid,
+        kind: EventKind,
+        message: Option<String>,
+    ) {
+        let event = PlaybookEvent {
+            seq: self.seq.fetch_add(1, Ordering::SeqCst) + 1,
+            playbook,
+            kind,
+            message,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+        };
+
+        let events = history.entry(playbook).or_default();
+        if events.len() == HISTORY_SIZE {
+            events.pop_front();
+        }
+        events.push_back(event.clone());
+
+        // It's fine to have no subscribers at all.
+        let _ = self.sender.send(event);
+    }
+
+    fn state_of(history: &HashMap<Uuid, VecDeque<PlaybookEvent>>, playbook: Uuid) -> Option<EventKind> {
+        history.get(&playbook)?.iter().rev().map(|e| e.kind).find(EventKind::is_state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resumes_after_the_events_of_this_process_only() {
+        let bus = EventBus::default();
+        let playbook = Uuid::new_v4();
+        bus.publish(playbook, EventKind::Created, None);
+        bus.publish(playbook, EventKind::Building, None);
+
+        let first = bus.since(playbook, 0).remove(0);
+        assert_eq!(bus.resume_after(&bus.event_id(&first)), Some(1));
+
+        // The sequence numbers of a previous process, even a larger one, replay everything since the restart.
+        let other = EventBus::default();
+        assert_eq!(bus.resume_after(&format!("{:08x}-1", other.epoch.wrapping_add(1))), Some(0));
+        assert_eq!(bus.resume_after(&format!("{:08x}-99", bus.epoch)), Some(0));
+        assert_eq!(bus.resume_after("42"), None);
+    }
+
+    #[test]
+    fn polls_once_for_all_the_watchers() {
+        let bus = EventBus::default();
+        let playbook = Uuid::new_v4();
+        assert!(bus.watch(playbook));
+        assert!(!bus.watch(playbook));
+
+        bus.unwatch(playbook);
+        assert!(bus.keep_polling(playbook));
+        bus.unwatch(playbook);
+        assert!(!bus.keep_polling(playbook));
+
+        // The next watcher polls it again.
+        assert!(bus.watch(playbook));
+    }
+
+    #[test]
+    fn forgets_a_deleted_playbook() {
+        let bus = EventBus::default();
+        let playbook = Uuid::new_v4();
+        let mut receiver = bus.subscribe();
+        bus.transition(playbook, EventKind::Running, None);
+        bus.forget(playbook);
+
+        assert_eq!(receiver.try_recv().unwrap().kind, EventKind::Running);
+        assert_eq!(receiver.try_recv().unwrap().kind, EventKind::Deleted);
+        assert!(bus.since(playbook, 0).is_empty());
+        assert_eq!(bus.state(playbook), None);
+    }
+}
@@ -0,0 +1,356 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use amp_common::http::HTTPError;
+use amp_common::scm::client::Client as ScmClient;
+use amp_common::scm::driver::github;
+use amp_common::scm::errors::SCMError;
+use axum::http::header::ACCEPT;
+use axum::http::{HeaderMap, StatusCode};
+use reqwest::{RequestBuilder, Response};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::errors::{ApiError, Result, UPSTREAM_RETRY_AFTER};
+use crate::responses::diagnostics::TokenStatus;
+use crate::{monitor, telemetry};
+
+const RATE_LIMIT_LIMIT: &str = "x-ratelimit-limit";
+const RATE_LIMIT_REMAINING: &str = "x-ratelimit-remaining";
+const RATE_LIMIT_RESET: &str = "x-ratelimit-reset";
+const RATE_LIMIT_RESOURCE: &str = "x-ratelimit-resource";
+
+/// The GitHub tokens the SCM calls are spread across, with the rate limit each one has left.
+///
+/// The users don't sign in to GitHub through this API, so their calls are made with the configured
+/// tokens in turn, skipping the exhausted ones until their limits reset.
+pub struct TokenPool {
+    slots: Vec<Arc<Slot>>,
+    next: AtomicUsize,
+    /// When the rate limits were last refreshed from GitHub, see [`TokenPool::claim_refresh`].
+    refreshed: Mutex<Option<Instant>>,
+}
+
+struct Slot {
+    name: String,
+    token: Option<String>,
+    client: ScmClient,
+    http_client: reqwest::Client,
+    endpoint: String,
+    rate: RwLock<Option<RateLimit>>,
+    /// The search rate limit, apart from the core one of the other calls.
+    search: RwLock<Option<RateLimit>>,
+}
+
+/// The rate limits of a token GitHub tells apart, see <https://docs.github.com/rest/rate-limit>.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Resource {
+    Core,
+    Search,
+}
+
+/// A rate limit of a token, as last told by GitHub.
+#[derive(Clone, Copy, Debug)]
+struct RateLimit {
+    limit: u64,
+    remaining: u64,
+    /// When (in seconds since the epoch) the limit resets.
+    reset: u64,
+}
+
+/// A token taken from the pool, for the SCM calls of one request.
+#[derive(Clone)]
+pub struct GitHub {
+    slot: Arc<Slot>,
+}
+
+impl TokenPool {
+    /// Build a client for each of the pooled tokens, or for the single `AUTH_TOKEN` without a pool.
+    pub fn new(config: &Config, http_client: reqwest::Client) -> anyhow::Result<TokenPool> {
+        let mut tokens: Vec<Option<String>> = config
+            .github_tokens
+            .iter()
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+            .map(|t| Some(t.to_string()))
+            .collect();
+        if tokens.is_empty() {
+            tokens.push(config.auth_token.clone());
+        }
+
+        let mut slots = Vec::with_capacity(tokens.len());
+        for (i, token) in tokens.into_iter().enumerate() {
+            let name = match &token {
+                Some(_) => format!("token-{}", i + 1),
+                None => "anonymous".to_string(),
+            };
+            let client = ScmClient::new(github::new(&config.github_endpoint, token.clone())?);
+            let (http_client, endpoint) =
+                (http_client.clone(), config.github_endpoint.trim_end_matches('/').to_string());
+            let (rate, search) = (RwLock::new(None), RwLock::new(None));
+            slots.push(Arc::new(Slot { name, token, client, http_client, endpoint, rate, search }));
+        }
+
+        Ok(TokenPool { slots, next: AtomicUsize::new(0), refreshed: Mutex::new(None) })
+    }
+
+    /// Take the next token with some rate limit left, or tell when the first one resets.
+    pub fn take(&self) -> Result<GitHub> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let now = now();
+        for i in 0..self.slots.len() {
+            let slot = &self.slots[(start + i) % self.slots.len()];
+            if slot.exhausted(Resource::Core, now).is_none() {
+                return Ok(GitHub { slot: slot.clone() });
+            }
+        }
+
+        let reset = self.slots.iter().filter_map(|s| s.exhausted(Resource::Core, now)).min().unwrap_or(now);
+        Err(ApiError::GitHubRateLimited { reset })
+    }
+
+    /// All the tokens, e.g. to refresh their rate limits.
+    pub fn all(&self) -> Vec<GitHub> {
+        self.slots.iter().map(|slot| GitHub { slot: slot.clone() }).collect()
+    }
+
+    /// Whether the rate limits were last refreshed longer than the interval ago, claiming the refresh
+    /// if so, so the concurrent callers serve the rate limits known rather than refreshing them too.
+    pub fn claim_refresh(&self, interval: Duration) -> bool {
+        let mut refreshed = self.refreshed.lock().unwrap_or_else(PoisonError::into_inner);
+        if refreshed.is_some_and(|at| at.elapsed() < interval) {
+            return false;
+        }
+
+        *refreshed = Some(Instant::now());
+        true
+    }
+}
+
+impl Slot {
+    fn limit(&self, resource: Resource) -> &RwLock<Option<RateLimit>> {
+        match resource {
+            Resource::Core => &self.rate,
+            Resource::Search => &self.search,
+        }
+    }
+
+    /// When the limit resets, if the token has none left.
+    fn exhausted(&self, resource: Resource, now: u64) -> Option<u64> {
+        let rate = (*self.limit(resource).read().ok()?)?;
+        (rate.remaining == 0 && rate.reset > now).then_some(rate.reset)
+    }
+
+    /// Count a call made without telling the rate limit, as GitHub does, until a response tells it exactly.
+    fn spend(&self, now: u64) {
+        let Ok(mut current) = self.rate.write() else { return };
+        // Once the limit resets, how much is left is unknown until the next response.
+        let Some(rate) = current.as_mut().filter(|r| r.reset > now) else { return };
+        rate.remaining = rate.remaining.saturating_sub(1);
+        metrics::gauge!("playground_github_rate_limit_remaining", "token" => self.name.clone())
+            .set(rate.remaining as f64);
+    }
+
+    fn update(&self, resource: Resource, rate: RateLimit) {
+        if resource == Resource::Core {
+            metrics::gauge!("playground_github_rate_limit_remaining", "token" => self.name.clone())
+                .set(rate.remaining as f64);
+        }
+        if let Ok(mut current) = self.limit(resource).write() {
+            *current = Some(rate);
+        }
+    }
+}
+
+impl GitHub {
+    pub fn client(&self) -> &ScmClient {
+        &self.slot.client
+    }
+
+    /// The token to authenticate the requests sent to GitHub directly.
+    pub fn token(&self) -> Option<&str> {
+        self.slot.token.as_deref()
+    }
+
+    /// Make a call with the SCM client, measured like the other SCM calls.
+    ///
+    /// The SCM client doesn't tell the rate limit headers of its responses, so the call is counted
+    /// against the rate limit last known instead, keeping the exhausted tokens from being taken.
+    pub async fn call<T>(
+        &self,
+        operation: &'static str,
+        call: impl Future<Output = Result<T, SCMError>>,
+    ) -> Result<T, SCMError> {
+        let result = monitor::scm(operation, call).await;
+        self.slot.spend(now());
+
+        result
+    }
+
+    /// Build a request to the GitHub REST API, for what the SCM client doesn't cover.
+    pub fn get(&self, path: &str) -> RequestBuilder {
+        let request =
+            self.slot.http_client.get(format!("{}{}", self.slot.endpoint, path)).headers(telemetry::headers());
+        match &self.slot.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Send a request built by [`GitHub::get`], tracking the rate limit told by the response,
+    /// and failing on the error statuses like the SCM client does.
+    pub async fn send(&self, operation: &'static str, request: RequestBuilder) -> Result<Response, SCMError> {
+        monitor::scm(operation, async {
+            let response =
+                request.send().await.map_err(|e| SCMError::ClientError(HTTPError::Transport(0, e.to_string())))?;
+            let status = response.status();
+            // The errors are told apart by the rate limit tracked here, see `GitHub::error`.
+            let _ = self.observe(status, response.headers());
+            if status.is_success() || status.is_redirection() {
+                return Ok(response);
+            }
+
+            let message = response.text().await.unwrap_or_default();
+            Err(SCMError::ClientError(match status {
+                StatusCode::NOT_FOUND => HTTPError::NotFound(message),
+                _ => HTTPError::Transport(status.as_u16(), message),
+            }))
+        })
+        .await
+    }
+
+    /// Search the repositories by keywords, see <https://docs.github.com/rest/search/search#search-repositories>.
+    pub async fn search_repositories(&self, q: &str, page: u32, per_page: u32) -> Result<SearchResult, SCMError> {
+        let request = self.get("/search/repositories").header(ACCEPT, "application/vnd.github+json").query(&[
+            ("q", q.to_string()),
+            ("page", page.to_string()),
+            ("per_page", per_page.to_string()),
+        ]);
+
+        self.send("search.repositories", request)
+            .await?
+            .json()
+            .await
+            .map_err(|e| SCMError::ClientError(HTTPError::Deserialization(e.to_string())))
+    }
+
+    /// Track the rate limit told by a response from GitHub, and turn it into an error once exceeded.
+    pub fn observe(&self, status: StatusCode, headers: &HeaderMap) -> Result<()> {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).and_then(|v| v.parse::<u64>().ok());
+        let (Some(limit), Some(remaining), Some(reset)) =
+            (header(RATE_LIMIT_LIMIT), header(RATE_LIMIT_REMAINING), header(RATE_LIMIT_RESET))
+        else {
+            return match status {
+                StatusCode::TOO_MANY_REQUESTS => {
+                    Err(ApiError::GitHubRateLimited { reset: now() + UPSTREAM_RETRY_AFTER })
+                }
+                _ => Ok(()),
+            };
+        };
+
+        // The search has a limit of its own, which says nothing about the other calls.
+        match headers.get(RATE_LIMIT_RESOURCE).and_then(|v| v.to_str().ok()) {
+            None | Some("core") => self.slot.update(Resource::Core, RateLimit { limit, remaining, reset }),
+            Some("search") => self.slot.update(Resource::Search, RateLimit { limit, remaining, reset }),
+            Some(_) => {}
+        }
+
+        let exceeded = status == StatusCode::TOO_MANY_REQUESTS || (status == StatusCode::FORBIDDEN && remaining == 0);
+        match exceeded {
+            true => Err(ApiError::GitHubRateLimited { reset }),
+            false => Ok(()),
+        }
+    }
+
+    /// Map the error of an SCM call, telling the exceeded rate limit apart from the other errors,
+    /// which the SCM client reports as a plain 403 or 429.
+    pub fn error(&self, e: SCMError, otherwise: impl FnOnce(SCMError) -> ApiError) -> ApiError {
+        match self.rate_limited(Resource::Core, &e) {
+            Some(error) => error,
+            None => otherwise(e),
+        }
+    }
+
+    /// Map the error of a search like [`GitHub::error`], against the search rate limit, so exceeding it
+    /// leaves the token usable for the other calls.
+    pub fn search_error(&self, e: SCMError, otherwise: impl FnOnce(SCMError) -> ApiError) -> ApiError {
+        match self.rate_limited(Resource::Search, &e) {
+            Some(error) => error,
+            None => otherwise(e),
+        }
+    }
+
+    /// Returns the error to respond with if the SCM call failed as the rate limit is exceeded.
+    fn rate_limited(&self, resource: Resource, e: &SCMError) -> Option<ApiError> {
+        let now = now();
+        let exceeded = match e {
+            SCMError::ClientError(HTTPError::Transport(429, _)) => true,
+            SCMError::ClientError(HTTPError::Transport(403, message)) => {
+                message.to_lowercase().contains("rate limit") || self.slot.exhausted(resource, now).is_some()
+            }
+            _ => false,
+        };
+        if !exceeded {
+            return None;
+        }
+
+        // Without the headers, assume the limit resets in a while, the next response tells exactly.
+        let rate = self.slot.limit(resource).read().ok().and_then(|r| *r);
+        let reset = rate.map(|r| r.reset).filter(|reset| *reset > now).unwrap_or(now + UPSTREAM_RETRY_AFTER);
+        self.slot.update(resource, RateLimit { limit: rate.map_or(0, |r| r.limit), remaining: 0, reset });
+        warn!("The GitHub {:?} rate limit of {} is exceeded until {}", resource, self.slot.name, reset);
+
+        Some(ApiError::GitHubRateLimited { reset })
+    }
+
+    /// The rate limit of the token as last told by GitHub.
+    pub fn status(&self) -> TokenStatus {
+        let rate = self.slot.rate.read().ok().and_then(|r| *r);
+        TokenStatus {
+            name: self.slot.name.clone(),
+            authenticated: self.slot.token.is_some(),
+            limit: rate.map(|r| r.limit),
+            remaining: rate.map(|r| r.remaining),
+            reset: rate.map(|r| r.reset),
+            exhausted: self.slot.exhausted(Resource::Core, now()).is_some(),
+        }
+    }
+}
+
+/// The response of the GitHub search repositories API.
+#[derive(Debug, Deserialize)]
+pub struct SearchResult {
+    pub total_count: u64,
+    pub items: Vec<SearchItem>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchItem {
+    pub full_name: String,
+    pub description: Option<String>,
+    pub stargazers_count: u64,
+    pub default_branch: String,
+    pub clone_url: String,
+}
+
+/// The current time in seconds since the epoch, like the reset times of GitHub.
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
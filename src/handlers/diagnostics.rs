@@ -0,0 +1,37 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::IntoResponse;
+
+use crate::context::Context;
+use crate::extract::Json;
+use crate::responses::diagnostics::GitHubDiagnosticsResponse;
+use crate::services::DiagnosticsService;
+
+// The Diagnostics Service Handlers.
+
+/// Returns the GitHub rate limit left on each of the tokens, refreshed from GitHub every 30 seconds at most.
+#[utoipa::path(
+    get, path = "/v1/diagnostics/github",
+    responses(
+        (status = 200, description = "The rate limits of the GitHub tokens", body = GitHubDiagnosticsResponse),
+    ),
+    tag = "Diagnostics"
+)]
+pub async fn github(State(ctx): State<Arc<Context>>) -> impl IntoResponse {
+    Json(DiagnosticsService::github(ctx).await)
+}
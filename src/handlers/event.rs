@@ -0,0 +1,65 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::{stream, Stream};
+use uuid::Uuid;
+
+use crate::context::Context;
+use crate::errors::{ApiError, Result};
+use crate::extract::Path;
+use crate::monitor::ActiveStream;
+use crate::services::EventService;
+
+// The Events Service Handlers.
+
+/// Watch the lifecycle events of a playbook.
+///
+/// The events are `created`, `building`, `running`, `failed`, `stopped`, `deleted` and `file-synced`,
+/// a reconnecting client sends the `Last-Event-ID` header to receive the events it missed.
+/// The stream ends once the playbook is deleted.
+#[utoipa::path(
+    get, path = "/v1/playbooks/{id}/events",
+    params(
+        ("id" = Uuid, description = "The id of playbook"),
+        ("Last-Event-ID" = Option<String>, Header, description = "The id of the last event received"),
+    ),
+    responses(
+        (status = 200, description = "Playbook events stream"),
+        (status = 404, description = "Playbook not found")
+    ),
+    tag = "Events"
+)]
+pub async fn events(
+    Path(id): Path<Uuid>,
+    State(ctx): State<Arc<Context>>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = std::result::Result<Event, axum::Error>>>, ApiError> {
+    let last = headers.get("last-event-id").and_then(|v| v.to_str().ok());
+    let receiver = EventService::watch(ctx.clone(), id, last).await?;
+
+    // Held by the stream, so the gauge goes down when the client disconnects or the stream ends.
+    let active = ActiveStream::open("playground_event_streams_active");
+    let stream = stream::unfold((receiver, ctx.events.clone(), active), |(mut receiver, bus, active)| async move {
+        let event = receiver.recv().await?;
+        let sse = Event::default().id(bus.event_id(&event)).event(event.kind.as_str()).json_data(&event);
+        Some((sse, (receiver, bus, active)))
+    });
+
+    Ok(Sse::new(ctx.shutdown.drain(stream)).keep_alive(KeepAlive::default()))
+}
@@ -14,14 +14,14 @@
 
 use std::sync::Arc;
 
-use axum::extract::{Path, State};
+use axum::extract::State;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
-use axum::Json;
 use uuid::Uuid;
 
 use crate::context::Context;
 use crate::errors::Result;
+use crate::extract::{Json, Path};
 use crate::requests::file::{DestinationRequest, FileRequest};
 use crate::services::FileService;
 use amp_common::scm::content::Content;
@@ -61,6 +61,7 @@ pub async fn get(State(ctx): State<Arc<Context>>, Path((id, path)): Path<(Uuid,
     ),
     responses(
         (status = 201, description = "The file created successfully", body = Content),
+        (status = 403, description = "Workspace quota exceeded"),
         (status = 404, description = "Playbook not found"),
         (status = 500, description = "Internal Server Error"),
     ),
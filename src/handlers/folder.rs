@@ -16,14 +16,14 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use amp_common::scm::git::Tree;
-use axum::extract::{Path, Query, State};
+use axum::extract::State;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
-use axum::Json;
 use uuid::Uuid;
 
 use crate::context::Context;
 use crate::errors::Result;
+use crate::extract::{Json, Path, Query};
 use crate::requests::file::DestinationRequest;
 use crate::services::FolderService;
 use amp_common::scm::content::{Content, File};
@@ -0,0 +1,69 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+
+use crate::context::Context;
+use crate::extract::Json;
+use crate::responses::health::{ReadinessResponse, VersionResponse};
+use crate::services::HealthService;
+
+// The Health Service Handlers.
+
+/// Liveness probe, succeeds as long as the server is serving requests.
+#[utoipa::path(
+    get, path = "/healthz",
+    responses(
+        (status = 200, description = "The server is alive"),
+    ),
+    tag = "Health"
+)]
+pub async fn healthz() -> impl IntoResponse {
+    StatusCode::OK
+}
+
+/// Readiness probe, checks that the Amphitheatre server and the SCM endpoint are reachable.
+///
+/// The dependencies are probed at most once per `AMP_READINESS_CACHE_TTL`, and a rate limited SCM
+/// is reported as degraded rather than unreachable.
+#[utoipa::path(
+    get, path = "/readyz",
+    responses(
+        (status = 200, description = "All the dependencies are reachable", body = ReadinessResponse),
+        (status = 503, description = "Some dependencies are unreachable", body = ReadinessResponse),
+    ),
+    tag = "Health"
+)]
+pub async fn readyz(State(ctx): State<Arc<Context>>) -> impl IntoResponse {
+    let report = HealthService::ready(ctx).await;
+    let status = if report.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+    (status, Json(report))
+}
+
+/// Returns the version of the server.
+#[utoipa::path(
+    get, path = "/version",
+    responses(
+        (status = 200, description = "The version of the server", body = VersionResponse),
+    ),
+    tag = "Health"
+)]
+pub async fn version() -> impl IntoResponse {
+    Json(HealthService::version())
+}
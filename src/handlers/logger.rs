@@ -15,13 +15,15 @@
 use std::convert::Infallible;
 use std::sync::Arc;
 
-use axum::extract::{Path, State};
+use axum::extract::State;
 use axum::response::sse::{Event, KeepAlive, Sse};
 use futures::{Stream, StreamExt};
 use uuid::Uuid;
 
 use crate::context::Context;
 use crate::errors::{ApiError, Result};
+use crate::extract::Path;
+use crate::monitor::ActiveStream;
 use crate::services::LoggerService;
 
 // The Logging Service Handlers.
@@ -42,10 +44,16 @@ pub async fn logs(
     Path(id): Path<Uuid>,
     State(ctx): State<Arc<Context>>,
 ) -> Result<Sse<impl Stream<Item = axum::response::Result<Event, Infallible>>>, ApiError> {
-    let event_source = LoggerService::logs(ctx, id).await?;
+    let event_source = LoggerService::logs(ctx.clone(), id).await?;
 
+    // Held by the stream, so the gauge goes down when the client disconnects.
+    let active = ActiveStream::open("playground_log_streams_active");
+    let activity = ctx.activity.clone();
     let stream = event_source
-        .map(|line| {
+        .map(move |line| {
+            let _ = &active;
+            // A streaming log is an activity, the playbook is in use.
+            activity.touch(id);
             if let Ok(reqwest_eventsource::Event::Message(message)) = line {
                 Event::default().data(message.data)
             } else {
@@ -54,5 +62,5 @@ pub async fn logs(
         })
         .map(Ok);
 
-    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+    Ok(Sse::new(ctx.shutdown.drain(stream)).keep_alive(KeepAlive::default()))
 }
@@ -0,0 +1,39 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use tracing::error;
+
+use crate::errors::{ApiError, Result};
+use crate::monitor;
+
+// The Metrics Service Handlers.
+
+/// Returns the metrics in the Prometheus text format.
+#[utoipa::path(
+    get, path = "/metrics",
+    responses(
+        (status = 200, description = "The metrics in the Prometheus text format", body = String, content_type = "text/plain"),
+    ),
+    tag = "Health"
+)]
+pub async fn metrics() -> Result<impl IntoResponse> {
+    let handle = monitor::install().map_err(|e| {
+        error!("Failed to install the Prometheus recorder: {}", e);
+        ApiError::InternalServerError
+    })?;
+
+    Ok(([(CONTENT_TYPE, "text/plain; version=0.0.4")], handle.render()))
+}
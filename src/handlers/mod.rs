@@ -12,7 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod diagnostics;
+pub mod event;
 pub mod file;
 pub mod folder;
+pub mod health;
 pub mod logger;
+pub mod metrics;
+pub mod operation;
 pub mod playbook;
+pub mod repo;
+pub mod snapshot;
+pub mod template;
+pub mod usage;
+
+use crate::errors::ApiError;
+
+/// The fallback of the routes not found.
+pub async fn not_found() -> ApiError {
+    ApiError::NotFound
+}
+
+/// The fallback of the routes found, but not with this method.
+pub async fn method_not_allowed() -> ApiError {
+    ApiError::MethodNotAllowed
+}
@@ -0,0 +1,52 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::header::LOCATION;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use uuid::Uuid;
+
+use crate::context::Context;
+use crate::errors::Result;
+use crate::extract::{Json, Path};
+use crate::operations::Operation;
+use crate::services::OperationService;
+use crate::user::User;
+
+// The Operations Service Handlers.
+
+/// Returns a long-running operation started by the user, with its progress and the result or error once finished.
+#[utoipa::path(
+    get, path = "/v1/operations/{op}",
+    params(
+        ("op" = Uuid, description = "The id of operation"),
+    ),
+    responses(
+        (status = 200, description = "The operation", body = Operation),
+        (status = 404, description = "Operation not found"),
+    ),
+    tag = "Operations"
+)]
+pub async fn get(State(ctx): State<Arc<Context>>, User(user): User, Path(op): Path<Uuid>) -> Result<impl IntoResponse> {
+    Ok(Json(OperationService::get(ctx, op, &user).await?))
+}
+
+/// Respond with `202 Accepted` and where to follow the operation.
+pub fn accepted(operation: Operation) -> Response {
+    let location = format!("/v1/operations/{}", operation.id);
+    (StatusCode::ACCEPTED, [(LOCATION, location)], Json(operation)).into_response()
+}
@@ -14,39 +14,103 @@
 
 use std::sync::Arc;
 
-use axum::extract::{Path, State};
+use axum::extract::State;
 use axum::http::StatusCode;
-use axum::response::IntoResponse;
-use axum::Json;
+use axum::response::{IntoResponse, Response};
 use uuid::Uuid;
 
 use amp_common::resource::PlaybookSpec;
 
 use crate::context::Context;
 use crate::errors::Result;
-use crate::requests::playbook::CreatePlaybookRequest;
-use crate::services::PlaybookService;
+use crate::extract::{Json, Path, Query};
+use crate::handlers::operation::accepted;
+use crate::operations::Operation;
+use crate::requests::playbook::{CreatePlaybookRequest, UpdatePlaybookRequest, WaitParams};
+use crate::responses::playbook::{UpdatePlaybookResponse, ValidationReport};
+use crate::services::{OperationService, PlaybookService};
+use crate::user::User;
 
 // The Playbooks Service Handlers.
 
 /// Create a playbook in the current account.
 #[utoipa::path(
     post, path = "/v1/playbooks",
+    params(WaitParams),
     request_body(
         content = inline(CreatePlaybookRequest),
         description = "Create playbook request",
         content_type = "application/json"
     ),
     responses(
-        (status = 201, description = "Playbook created successfully", body = PlaybookSpec)
+        (status = 201, description = "Playbook created successfully", body = PlaybookSpec),
+        (status = 202, description = "Playbook creation accepted", body = Operation),
+        (status = 400, description = "Bad playbook request"),
+        (status = 422, description = "The repository has no valid character manifest"),
+        (status = 429, description = "Playbooks quota exceeded"),
     ),
     tag = "Playbooks"
 )]
 pub async fn create(
+    State(ctx): State<Arc<Context>>,
+    User(user): User,
+    Query(params): Query<WaitParams>,
+    Json(req): Json<CreatePlaybookRequest>,
+) -> Result<Response> {
+    if params.wait == Some(false) {
+        return Ok(accepted(OperationService::create(ctx, req, user)));
+    }
+
+    Ok((StatusCode::CREATED, Json(PlaybookService::create(ctx, &req, &user).await?)).into_response())
+}
+
+/// Validate a playbook request without creating it (dry run).
+#[utoipa::path(
+    post, path = "/v1/playbooks/validate",
+    request_body(
+        content = inline(CreatePlaybookRequest),
+        description = "Create playbook request",
+        content_type = "application/json"
+    ),
+    responses(
+        (status = 200, description = "The validation report", body = ValidationReport),
+        (status = 400, description = "Bad playbook request"),
+        (status = 500, description = "Internal Server Error"),
+    ),
+    tag = "Playbooks"
+)]
+pub async fn validate(
     State(ctx): State<Arc<Context>>,
     Json(req): Json<CreatePlaybookRequest>,
 ) -> Result<impl IntoResponse> {
-    Ok((StatusCode::CREATED, Json(PlaybookService::create(ctx, &req).await?)))
+    Ok(Json(PlaybookService::validate(ctx, &req).await?))
+}
+
+/// Update a playbook's title, description or git reference.
+#[utoipa::path(
+    patch, path = "/v1/playbooks/{id}",
+    params(
+        ("id" = Uuid, description = "The id of playbook"),
+    ),
+    request_body(
+        content = inline(UpdatePlaybookRequest),
+        description = "Update playbook request",
+        content_type = "application/json"
+    ),
+    responses(
+        (status = 200, description = "Playbook updated successfully", body = UpdatePlaybookResponse),
+        (status = 400, description = "Bad playbook request"),
+        (status = 404, description = "Playbook not found"),
+        (status = 422, description = "The new reference has no valid character manifest"),
+    ),
+    tag = "Playbooks"
+)]
+pub async fn update(
+    State(ctx): State<Arc<Context>>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<UpdatePlaybookRequest>,
+) -> Result<impl IntoResponse> {
+    Ok(Json(PlaybookService::update(ctx, id, &req).await?))
 }
 
 /// Delete a playbook
@@ -73,16 +137,57 @@ pub async fn delete(State(ctx): State<Arc<Context>>, Path(id): Path<Uuid>) -> Re
     post, path = "/v1/playbooks/{id}/actions/start",
     params(
         ("id" = Uuid, description = "The id of playbook"),
+        WaitParams,
     ),
     responses(
         (status = 204, description = "Playbook started successfully"),
+        (status = 202, description = "Playbook start accepted", body = Operation),
+        (status = 403, description = "Running time quota exceeded"),
         (status = 404, description = "Playbook not found"),
         (status = 500, description = "Failed to start playbook")
     ),
     tag = "Playbooks"
 )]
-pub async fn start(State(ctx): State<Arc<Context>>, Path(id): Path<Uuid>) -> Result<impl IntoResponse> {
+pub async fn start(
+    State(ctx): State<Arc<Context>>,
+    User(user): User,
+    Path(id): Path<Uuid>,
+    Query(params): Query<WaitParams>,
+) -> Result<Response> {
+    if params.wait == Some(false) {
+        return Ok(accepted(OperationService::start(ctx, id, &user)));
+    }
+
     PlaybookService::start(ctx, id).await?;
 
-    Ok(StatusCode::NO_CONTENT)
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Fork a playbook, including the files changed on its workspace.
+#[utoipa::path(
+    post, path = "/v1/playbooks/{id}/actions/fork",
+    params(
+        ("id" = Uuid, description = "The id of playbook"),
+        WaitParams,
+    ),
+    responses(
+        (status = 201, description = "Playbook forked successfully", body = PlaybookSpec),
+        (status = 202, description = "Playbook fork accepted", body = Operation),
+        (status = 404, description = "Playbook not found"),
+        (status = 429, description = "Playbooks quota exceeded"),
+        (status = 500, description = "Failed to fork playbook")
+    ),
+    tag = "Playbooks"
+)]
+pub async fn fork(
+    State(ctx): State<Arc<Context>>,
+    User(user): User,
+    Path(id): Path<Uuid>,
+    Query(params): Query<WaitParams>,
+) -> Result<Response> {
+    if params.wait == Some(false) {
+        return Ok(accepted(OperationService::fork(ctx, id, user)));
+    }
+
+    Ok((StatusCode::CREATED, Json(PlaybookService::fork(ctx, id, &user).await?)).into_response())
 }
@@ -0,0 +1,46 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::IntoResponse;
+
+use crate::context::Context;
+use crate::errors::Result;
+use crate::extract::{Json, Query};
+use crate::requests::repo::SearchRepositoryParams;
+use crate::responses::repo::RepositorySearchResponse;
+use crate::services::RepoService;
+
+// The Repositories Service Handlers.
+
+/// Search repositories by keywords.
+#[utoipa::path(
+    get, path = "/v1/repos/search",
+    params(SearchRepositoryParams),
+    responses(
+        (status = 200, description = "The repositories found", body = RepositorySearchResponse),
+        (status = 400, description = "Bad search request"),
+        (status = 429, description = "GitHub rate limit exceeded"),
+        (status = 502, description = "Failed to search repositories"),
+    ),
+    tag = "Repositories"
+)]
+pub async fn search(
+    State(ctx): State<Arc<Context>>,
+    Query(params): Query<SearchRepositoryParams>,
+) -> Result<impl IntoResponse> {
+    Ok(Json(RepoService::search(ctx, &params).await?))
+}
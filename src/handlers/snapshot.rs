@@ -0,0 +1,86 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use uuid::Uuid;
+
+use amp_common::scm::content::Content;
+
+use crate::context::Context;
+use crate::errors::Result;
+use crate::extract::{Json, Path};
+use crate::responses::snapshot::SnapshotResponse;
+use crate::services::SnapshotService;
+
+// The Snapshots Service Handlers.
+
+/// Take a read-only snapshot of a playbook's files.
+#[utoipa::path(
+    post, path = "/v1/playbooks/{id}/snapshots",
+    params(
+        ("id" = Uuid, description = "The id of playbook"),
+    ),
+    responses(
+        (status = 201, description = "Snapshot created successfully", body = SnapshotResponse),
+        (status = 404, description = "Playbook not found"),
+        (status = 429, description = "GitHub rate limit exceeded"),
+        (status = 500, description = "Failed to create snapshot"),
+        (status = 502, description = "Failed to read the repository"),
+    ),
+    tag = "Snapshots"
+)]
+pub async fn create(State(ctx): State<Arc<Context>>, Path(id): Path<Uuid>) -> Result<impl IntoResponse> {
+    Ok((StatusCode::CREATED, Json(SnapshotService::create(ctx, id).await?)))
+}
+
+/// Returns a snapshot with its file tree.
+#[utoipa::path(
+    get, path = "/v1/snapshots/{slug}",
+    params(
+        ("slug" = String, description = "The slug of snapshot"),
+    ),
+    responses(
+        (status = 200, description = "The snapshot", body = SnapshotResponse),
+        (status = 404, description = "Snapshot not found"),
+    ),
+    tag = "Snapshots"
+)]
+pub async fn get(State(ctx): State<Arc<Context>>, Path(slug): Path<String>) -> Result<impl IntoResponse> {
+    Ok(Json(SnapshotService::get(ctx, slug).await?))
+}
+
+/// Returns a file's content from a snapshot.
+#[utoipa::path(
+    get, path = "/v1/snapshots/{slug}/files/{path}",
+    params(
+        ("slug" = String, description = "The slug of snapshot"),
+        ("path" = String, description = "The file path relative to the root of the workspace."),
+    ),
+    responses(
+        (status = 200, description = "The file content", body = Content),
+        (status = 404, description = "Snapshot not found"),
+        (status = 404, description = "File not found"),
+    ),
+    tag = "Snapshots"
+)]
+pub async fn file(
+    State(ctx): State<Arc<Context>>,
+    Path((slug, path)): Path<(String, String)>,
+) -> Result<impl IntoResponse> {
+    Ok(Json(SnapshotService::file(ctx, slug, path).await?))
+}
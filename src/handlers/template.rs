@@ -0,0 +1,37 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::IntoResponse;
+
+use crate::context::Context;
+use crate::extract::Json;
+use crate::services::TemplateService;
+use crate::templates::Template;
+
+// The Templates Service Handlers.
+
+/// Returns the playbook templates catalogue.
+#[utoipa::path(
+    get, path = "/v1/templates",
+    responses(
+        (status = 200, description = "The playbook templates", body = Vec<Template>),
+    ),
+    tag = "Templates"
+)]
+pub async fn list(State(ctx): State<Arc<Context>>) -> impl IntoResponse {
+    Json(TemplateService::list(&ctx))
+}
@@ -0,0 +1,42 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::IntoResponse;
+
+use crate::context::Context;
+use crate::errors::Result;
+use crate::extract::Json;
+use crate::responses::usage::UsageResponse;
+use crate::services::UsageService;
+use crate::user::User;
+
+// The Usage Service Handlers.
+
+/// Returns the current user's usage against the quotas.
+#[utoipa::path(
+    get, path = "/v1/me/usage",
+    params(
+        ("x-user-id" = Option<String>, Header, description = "The user, from the trusted proxies only"),
+    ),
+    responses(
+        (status = 200, description = "The current usage", body = UsageResponse),
+    ),
+    tag = "Usage"
+)]
+pub async fn get(State(ctx): State<Arc<Context>>, User(user): User) -> Result<impl IntoResponse> {
+    Ok(Json(UsageService::get(ctx, &user).await?))
+}
@@ -12,13 +12,32 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod activity;
 pub mod app;
 pub mod config;
 pub mod context;
 pub mod errors;
+pub mod events;
+pub mod extract;
+pub mod github;
 pub mod handlers;
+pub mod listener;
+pub mod middleware;
+pub mod monitor;
+pub mod operations;
+pub mod overlay;
+pub mod quota;
+pub mod reaper;
 pub mod requests;
+pub mod responses;
 pub mod routes;
+pub mod scm_cache;
 pub mod services;
+pub mod shutdown;
+pub mod snapshots;
 pub mod swagger;
+pub mod telemetry;
+pub mod templates;
+pub mod upstream;
+pub mod user;
 pub mod utils;
@@ -0,0 +1,262 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufReader};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Context as _};
+use axum::extract::connect_info::Connected;
+use axum::serve::{IncomingStream, Listener};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, warn};
+
+/// How often the certificate and key files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a client is given to complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The maximum number of handshaken connections waiting to be served.
+const PENDING_CONNECTIONS: usize = 128;
+
+/// Bind the TCP listener, with an error telling which address failed rather than a panic.
+pub async fn bind_tcp(addr: SocketAddr) -> anyhow::Result<TcpListener> {
+    TcpListener::bind(addr).await.with_context(|| format!("Failed to bind {}, is the port already in use?", addr))
+}
+
+/// Bind the Unix domain socket, replacing the socket file left over by a previous run.
+///
+/// The socket of an instance still running is left alone: it's only stale if nothing accepts
+/// the connections on it anymore.
+#[cfg(unix)]
+pub fn bind_unix(path: &Path) -> anyhow::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixStream;
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(anyhow!("Failed to bind {}, the file exists and is not a socket", path.display()));
+        }
+        match UnixStream::connect(path) {
+            Ok(_) => return Err(anyhow!("Failed to bind {}, another instance is listening on it", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                std::fs::remove_file(path)
+                    .with_context(|| format!("Failed to remove the stale socket {}", path.display()))?;
+            }
+            // Removed in the meantime, the bind tells if it's taken again.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to check the socket {}", path.display())),
+        }
+    }
+
+    tokio::net::UnixListener::bind(path).with_context(|| format!("Failed to bind {}", path.display()))
+}
+
+/// Build the TLS acceptor from the PEM certificate chain and private key, and reload them
+/// in the background whenever the files change, e.g. when the certificate is renewed.
+pub fn tls_acceptor(cert: PathBuf, key: PathBuf) -> anyhow::Result<TlsAcceptor> {
+    let resolver = Arc::new(CertificateResolver { current: RwLock::new(Arc::new(load(&cert, &key)?)) });
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_cert_resolver(resolver.clone());
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    tokio::spawn(reload(resolver, cert, key));
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Serves the current certificate, swapped on reload without restarting the listener.
+#[derive(Debug)]
+struct CertificateResolver {
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.current.read().ok().map(|current| current.clone())
+    }
+}
+
+fn load(cert: &Path, key: &Path) -> anyhow::Result<CertifiedKey> {
+    let file = File::open(cert).with_context(|| format!("Failed to open the certificate {}", cert.display()))?;
+    let chain = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse the certificate {}", cert.display()))?;
+    if chain.is_empty() {
+        return Err(anyhow!("No certificate found in {}", cert.display()));
+    }
+
+    let file = File::open(key).with_context(|| format!("Failed to open the private key {}", key.display()))?;
+    let der = rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to parse the private key {}", key.display()))?
+        .ok_or_else(|| anyhow!("No private key found in {}", key.display()))?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&der)
+        .with_context(|| format!("Unsupported private key {}", key.display()))?;
+
+    Ok(CertifiedKey::new(chain, signing_key))
+}
+
+async fn reload(resolver: Arc<CertificateResolver>, cert: PathBuf, key: PathBuf) {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last: (Option<SystemTime>, Option<SystemTime>) = (modified(&cert), modified(&key));
+
+    let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let current = (modified(&cert), modified(&key));
+        if current == last {
+            continue;
+        }
+        last = current;
+
+        // The files may be replaced one after the other, a mismatch is retried on the next change.
+        match load(&cert, &key) {
+            Ok(certified) => {
+                if let Ok(mut current) = resolver.current.write() {
+                    *current = Arc::new(certified);
+                    info!("Reloaded the TLS certificate from {}", cert.display());
+                }
+            }
+            Err(e) => warn!("Failed to reload the TLS certificate, keeping the previous one: {:#}", e),
+        }
+    }
+}
+
+/// Accept the TLS connections, with the handshakes running concurrently
+/// so a slow client can't hold up the others.
+///
+/// The connections are accepted until the shutdown resolves or the listener is dropped,
+/// then the TCP listener is closed, so the port is released while the requests drain.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    pub fn new<F>(mut listener: TcpListener, acceptor: TlsAcceptor, shutdown: F) -> io::Result<TlsListener>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let local_addr = listener.local_addr()?;
+        let (sender, connections) = mpsc::channel(PENDING_CONNECTIONS);
+
+        tokio::spawn(async move {
+            tokio::pin!(shutdown);
+            loop {
+                let (stream, addr) = tokio::select! {
+                    _ = &mut shutdown => break,
+                    _ = sender.closed() => break,
+                    accepted = Listener::accept(&mut listener) => accepted,
+                };
+                let (acceptor, sender) = (acceptor.clone(), sender.clone());
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send((stream, addr)).await;
+                        }
+                        Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", addr, e),
+                        Err(_) => debug!("TLS handshake with {} timed out", addr),
+                    }
+                });
+            }
+        });
+
+        Ok(TlsListener { local_addr, connections })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// The IP address of the client, unknown on the Unix domain socket.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientAddr(pub Option<IpAddr>);
+
+impl Connected<IncomingStream<'_, TcpListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        ClientAddr(Some(stream.remote_addr().ip()))
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        ClientAddr(Some(stream.remote_addr().ip()))
+    }
+}
+
+#[cfg(unix)]
+impl Connected<IncomingStream<'_, tokio::net::UnixListener>> for ClientAddr {
+    fn connect_info(_: IncomingStream<'_, tokio::net::UnixListener>) -> Self {
+        ClientAddr(None)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn only_replaces_a_stale_socket() {
+        let path = std::env::temp_dir().join(format!("playground-{}.sock", uuid::Uuid::new_v4()));
+
+        let live = bind_unix(&path).unwrap();
+        assert!(bind_unix(&path).is_err());
+        assert!(path.exists());
+
+        // The socket file is left behind once the instance is gone.
+        drop(live);
+        assert!(path.exists());
+        let _listener = bind_unix(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn keeps_a_file_which_is_not_a_socket() {
+        let path = std::env::temp_dir().join(format!("playground-{}.sock", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"").unwrap();
+
+        assert!(bind_unix(&path).is_err());
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
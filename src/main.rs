@@ -17,26 +17,28 @@
 use std::sync::Arc;
 
 use clap::Parser;
-use playground::{app, config::Config, context::Context};
-use tracing::metadata::LevelFilter;
-use tracing_subscriber::EnvFilter;
+use playground::{app, config::Config, context::Context, telemetry};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let filter = EnvFilter::builder().with_default_directive(LevelFilter::DEBUG.into()).from_env_lossy();
-    tracing_subscriber::fmt().with_env_filter(filter).init();
-
     // This returns an error if the `.env` file doesn't exist, but that's not what we want
     // since we're not going to use a `.env` file if we deploy this application.
     dotenv::dotenv().ok();
 
     // Parse our configuration from the environment.
     // This will exit with a help message if something is wrong.
-    // Then, initialize the shared context.
-    let ctx = Arc::new(Context::new(Config::parse()).await?);
+    let config = Config::parse();
+    config.validate()?;
+
+    // Install the tracing subscriber, exporting the spans if configured.
+    let provider = telemetry::init(&config)?;
+
+    // Initialize the shared context.
+    let ctx = Arc::new(Context::new(config).await?);
 
-    // Running the application in a loop.
-    app::run(ctx.clone()).await;
+    // Running the application until it's shut down.
+    let result = app::run(ctx.clone()).await;
 
-    Ok(())
+    telemetry::shutdown(provider);
+    result
 }
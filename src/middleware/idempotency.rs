@@ -0,0 +1,176 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::{to_bytes, Body, Bytes};
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use futures::StreamExt;
+use moka::future::Cache;
+use sha2::{Digest, Sha256};
+
+use crate::context::Context;
+use crate::errors::ApiError;
+use crate::user::{User, ANONYMOUS};
+
+/// The header carrying the client generated key of a mutating request.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// The header marking a response replayed from a previous request.
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// The maximum number of stored responses.
+const CAPACITY: u64 = 10_000;
+
+/// The maximum size of the response bodies to store.
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// The responses of the requests with an `Idempotency-Key`, keyed by user, method, path and key.
+#[derive(Clone)]
+pub struct IdempotencyStore {
+    entries: Cache<String, Arc<Entry>>,
+}
+
+#[derive(Debug)]
+enum Entry {
+    /// The first request is still being processed.
+    InFlight {
+        hash: String,
+    },
+    Completed {
+        hash: String,
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Bytes,
+    },
+}
+
+impl IdempotencyStore {
+    pub fn new(ttl: Duration) -> IdempotencyStore {
+        IdempotencyStore { entries: Cache::builder().max_capacity(CAPACITY).time_to_live(ttl).build() }
+    }
+}
+
+/// Replay the first response for the retries of a mutating request with the same `Idempotency-Key`,
+/// query and body, and reject the reuse of a key with a different query or body.
+pub async fn idempotency(State(ctx): State<Arc<Context>>, req: Request, next: Next) -> Response {
+    if !matches!(*req.method(), Method::POST | Method::PUT | Method::PATCH | Method::DELETE) {
+        return next.run(req).await;
+    }
+    let Some(key) = req.headers().get(IDEMPOTENCY_KEY_HEADER).and_then(|v| v.to_str().ok()).map(str::to_string) else {
+        return next.run(req).await;
+    };
+
+    let user = req.extensions().get::<User>().map_or(ANONYMOUS, |User(user)| user.as_str());
+    let key = format!("{}:{}:{}:{}", user, req.method(), req.uri().path(), key);
+
+    let (parts, body) = req.into_parts();
+    // the routes limit their bodies on their own, none of them accepts more than the uploads.
+    let limit = ctx.config.max_upload_size.max(ctx.config.max_body_size);
+    let Ok(body) = to_bytes(body, limit).await else {
+        return ApiError::PayloadTooLarge(format!("The body is larger than {} bytes", limit)).into_response();
+    };
+    // The query is part of the request too, the key sent with another one is reused as well.
+    let uri = parts.uri.path_and_query().map_or(parts.uri.path(), |uri| uri.as_str());
+    let hash = hex::encode(Sha256::new().chain_update(uri).chain_update([0]).chain_update(&body).finalize());
+
+    let store = &ctx.idempotency.entries;
+    let entry =
+        store.entry(key.clone()).or_insert_with(async { Arc::new(Entry::InFlight { hash: hash.clone() }) }).await;
+    if !entry.is_fresh() {
+        return match entry.into_value().as_ref() {
+            Entry::InFlight { hash: h } | Entry::Completed { hash: h, .. } if *h != hash => {
+                ApiError::IdempotencyKeyReused.into_response()
+            }
+            Entry::InFlight { .. } => ApiError::IdempotencyKeyInFlight.into_response(),
+            Entry::Completed { status, headers, body, .. } => {
+                let mut response = (*status, headers.clone(), body.clone()).into_response();
+                response.headers_mut().insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+                response
+            }
+        };
+    }
+
+    let pending = Pending { store: store.clone(), key: Some(key) };
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    // The server errors are not stored, so the client can retry them for real, nor the rejected requests,
+    // so it can fix them and retry with the same key. The conflicts are, they would only conflict again.
+    let status = response.status();
+    let rejected =
+        status.is_client_error() && !matches!(status, StatusCode::CONFLICT | StatusCode::UNPROCESSABLE_ENTITY);
+    if status.is_server_error() || rejected {
+        pending.forget().await;
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let mut stream = body.into_data_stream();
+    let (mut chunks, mut size) = (Vec::new(), 0);
+    while let Some(chunk) = stream.next().await {
+        // A response too large to be stored, or failing midway, is passed through as is.
+        let chunk = match chunk {
+            Ok(chunk) if size + chunk.len() <= MAX_BODY_SIZE => chunk,
+            chunk => {
+                pending.forget().await;
+                let head = futures::stream::iter(chunks.into_iter().map(Ok));
+                let body = head.chain(futures::stream::once(async { chunk })).chain(stream);
+                return Response::from_parts(parts, Body::from_stream(body));
+            }
+        };
+        size += chunk.len();
+        chunks.push(chunk);
+    }
+
+    let body = Bytes::from(chunks.concat());
+    let entry = Entry::Completed { hash, status: parts.status, headers: parts.headers.clone(), body: body.clone() };
+    pending.complete(entry).await;
+
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// The key of a request being processed, forgotten unless its response gets stored, so a request
+/// which never completes, e.g. cancelled by the client, timed out or panicking, doesn't leave its
+/// key in flight until it expires.
+struct Pending {
+    store: Cache<String, Arc<Entry>>,
+    key: Option<String>,
+}
+
+impl Pending {
+    async fn complete(mut self, entry: Entry) {
+        if let Some(key) = self.key.take() {
+            self.store.insert(key, Arc::new(entry)).await;
+        }
+    }
+
+    async fn forget(mut self) {
+        if let Some(key) = self.key.take() {
+            self.store.invalidate(&key).await;
+        }
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let store = self.store.clone();
+            tokio::spawn(async move { store.invalidate(&key).await });
+        }
+    }
+}
@@ -0,0 +1,31 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::context::Context;
+use crate::listener::ClientAddr;
+use crate::user::User;
+
+/// Resolve the user of the request once, for the handlers, the limits and the logs to agree on.
+pub async fn identify(State(ctx): State<Arc<Context>>, mut req: Request, next: Next) -> Response {
+    let peer = req.extensions().get::<ConnectInfo<ClientAddr>>().and_then(|ConnectInfo(addr)| addr.0);
+    let user = User::resolve(req.headers(), peer, &ctx.config.trusted_proxies);
+    req.extensions_mut().insert(user);
+
+    next.run(req).await
+}
@@ -0,0 +1,249 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{HeaderMap, HeaderName, Method};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use moka::future::Cache;
+
+use crate::context::Context;
+use crate::errors::ApiError;
+use crate::user::{User, ANONYMOUS};
+
+/// The maximum number of clients tracked at once, the least recent ones are forgotten first.
+const CAPACITY: u64 = 100_000;
+
+/// The window the budgets are given for, a bucket refills completely within it.
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// The routes never limited, so the probes and scrapers keep working under load.
+const EXEMPT: [&str; 4] = ["/healthz", "/readyz", "/version", "/metrics"];
+
+/// The headers telling the clients about their budget, see draft-ietf-httpapi-ratelimit-headers.
+pub const RATE_LIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+pub const RATE_LIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+pub const RATE_LIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// The separate budgets of the requests, as some cost the upstream and GitHub much more than others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Budget {
+    Cheap,
+    Expensive,
+}
+
+impl Budget {
+    /// Creating and forking playbooks provisions them upstream, and the recursive trees
+    /// walk the whole repository on GitHub, which the tree endpoint does given any `recursive` value.
+    fn of(method: &Method, route: &str, query: Option<&str>) -> Budget {
+        let recursive = || {
+            let mut params = query.map(|q| url::form_urlencoded::parse(q.as_bytes())).into_iter().flatten();
+            params.any(|(name, _)| name == "recursive")
+        };
+        match (method, route) {
+            (&Method::POST, "/v1/playbooks") | (&Method::POST, "/v1/playbooks/{id}/actions/fork") => Budget::Expensive,
+            (&Method::GET, "/v1/playbooks/{id}/tree") if recursive() => Budget::Expensive,
+            _ => Budget::Cheap,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Budget::Cheap => "cheap",
+            Budget::Expensive => "expensive",
+        }
+    }
+}
+
+/// The token buckets of the clients, keyed by budget and user or client IP.
+#[derive(Clone)]
+pub struct RateLimiter {
+    buckets: Cache<(Budget, String), Arc<Mutex<Bucket>>>,
+    cheap: u32,
+    expensive: u32,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// The outcome of taking a token, told to the client in the `RateLimit-*` headers.
+#[derive(Debug)]
+struct Decision {
+    allowed: bool,
+    limit: u32,
+    remaining: u32,
+    /// How long (in seconds) until the bucket is full again.
+    reset: u64,
+    /// How long (in seconds) until the next token is available.
+    retry_after: u64,
+}
+
+impl RateLimiter {
+    /// Allow `cheap` and `expensive` requests per minute to each client, 0 for unlimited.
+    pub fn new(cheap: u32, expensive: u32) -> RateLimiter {
+        // An idle bucket is full again after the window, forgetting it changes nothing.
+        let buckets = Cache::builder().max_capacity(CAPACITY).time_to_idle(WINDOW).build();
+        RateLimiter { buckets, cheap, expensive }
+    }
+
+    async fn acquire(&self, budget: Budget, client: String) -> Option<Decision> {
+        let limit = match budget {
+            Budget::Cheap => self.cheap,
+            Budget::Expensive => self.expensive,
+        };
+        if limit == 0 {
+            return None;
+        }
+
+        let bucket = self
+            .buckets
+            .get_with((budget, client), async { Arc::new(Mutex::new(Bucket::full(limit, Instant::now()))) })
+            .await;
+        let mut bucket = bucket.lock().unwrap_or_else(PoisonError::into_inner);
+
+        Some(bucket.take(limit, Instant::now()))
+    }
+}
+
+impl Bucket {
+    fn full(limit: u32, now: Instant) -> Bucket {
+        Bucket { tokens: f64::from(limit), updated: now }
+    }
+
+    /// Refill the tokens earned since the last request, up to the limit, and take one if there is any.
+    fn take(&mut self, limit: u32, now: Instant) -> Decision {
+        let capacity = f64::from(limit);
+        let rate = capacity / WINDOW.as_secs_f64();
+
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(capacity);
+        self.updated = now;
+
+        let allowed = self.tokens >= 1.0;
+        if allowed {
+            self.tokens -= 1.0;
+        }
+
+        Decision {
+            allowed,
+            limit,
+            remaining: self.tokens.floor() as u32,
+            reset: ((capacity - self.tokens) / rate).ceil() as u64,
+            retry_after: ((1.0 - self.tokens).max(0.0) / rate).ceil() as u64,
+        }
+    }
+}
+
+/// Limit the requests of each user, or each client IP for the anonymous ones, with a token bucket
+/// per budget, answering 429 with `Retry-After` once it's empty.
+///
+/// The clients are told apart by the user resolved by the `identify` middleware, which is the
+/// client IP for the requests not coming through a trusted proxy.
+pub async fn rate_limit(State(ctx): State<Arc<Context>>, req: Request, next: Next) -> Response {
+    let Some(route) = req.extensions().get::<MatchedPath>().map(|p| p.as_str().to_string()) else {
+        return next.run(req).await;
+    };
+    if EXEMPT.contains(&route.as_str()) {
+        return next.run(req).await;
+    }
+
+    let budget = Budget::of(req.method(), &route, req.uri().query());
+    let client = req.extensions().get::<User>().map_or(ANONYMOUS, |User(user)| user.as_str()).to_string();
+    let Some(decision) = ctx.rate_limiter.acquire(budget, client).await else {
+        return next.run(req).await;
+    };
+
+    let mut response = if decision.allowed {
+        next.run(req).await
+    } else {
+        let error =
+            ApiError::RateLimited { budget: budget.as_str(), limit: decision.limit, retry_after: decision.retry_after };
+        error.into_response()
+    };
+    headers(response.headers_mut(), &decision);
+
+    response
+}
+
+fn headers(headers: &mut HeaderMap, decision: &Decision) {
+    headers.insert(RATE_LIMIT_LIMIT, decision.limit.into());
+    headers.insert(RATE_LIMIT_REMAINING, decision.remaining.into());
+    headers.insert(RATE_LIMIT_RESET, decision.reset.into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_the_expensive_requests() {
+        assert_eq!(Budget::of(&Method::POST, "/v1/playbooks", None), Budget::Expensive);
+        assert_eq!(Budget::of(&Method::POST, "/v1/playbooks/{id}/actions/fork", None), Budget::Expensive);
+        assert_eq!(Budget::of(&Method::GET, "/v1/playbooks", None), Budget::Cheap);
+
+        let tree = |query| Budget::of(&Method::GET, "/v1/playbooks/{id}/tree", query);
+        assert_eq!(tree(None), Budget::Cheap);
+        assert_eq!(tree(Some("recursive")), Budget::Expensive);
+        assert_eq!(tree(Some("recursive=1")), Budget::Expensive);
+        assert_eq!(tree(Some("page=2&recursive=true")), Budget::Expensive);
+        // As recursive as the tree endpoint serves them.
+        assert_eq!(tree(Some("recursive=false")), Budget::Expensive);
+        assert_eq!(tree(Some("recursive=0")), Budget::Expensive);
+        assert_eq!(tree(Some("recursively=true")), Budget::Cheap);
+    }
+
+    #[test]
+    fn takes_the_tokens_until_the_bucket_is_empty() {
+        let now = Instant::now();
+        let mut bucket = Bucket::full(2, now);
+
+        let first = bucket.take(2, now);
+        assert!(first.allowed);
+        assert_eq!((first.limit, first.remaining, first.retry_after), (2, 1, 0));
+        assert_eq!(first.reset, 30);
+
+        let second = bucket.take(2, now);
+        assert!(second.allowed);
+        assert_eq!((second.remaining, second.reset), (0, 60));
+
+        let denied = bucket.take(2, now);
+        assert!(!denied.allowed);
+        assert_eq!((denied.remaining, denied.retry_after), (0, 30));
+    }
+
+    #[test]
+    fn refills_the_bucket_over_the_window() {
+        let now = Instant::now();
+        let mut bucket = Bucket::full(60, now);
+        for _ in 0..60 {
+            assert!(bucket.take(60, now).allowed);
+        }
+        assert!(!bucket.take(60, now).allowed);
+
+        // A token a second, up to the limit however long the bucket is idle.
+        let decision = bucket.take(60, now + Duration::from_secs(1));
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 0);
+
+        let decision = bucket.take(60, now + Duration::from_secs(600));
+        assert!(decision.allowed);
+        assert_eq!((decision.remaining, decision.reset), (59, 1));
+    }
+}
@@ -0,0 +1,73 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::extract::{RawPathParams, Request};
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::RequestExt;
+use uuid::Uuid;
+
+/// The header carrying the id of the request, from the client or generated.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The longest id accepted from the client, the longer ones are replaced.
+const MAX_LENGTH: usize = 128;
+
+/// The id of the request, available from the request extensions.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+/// What is known of the request being handled, to give the errors some context.
+#[derive(Clone, Debug, Default)]
+pub struct RequestScope {
+    pub id: String,
+    pub path: String,
+    /// The parameters of the matched route, e.g. the playbook id and the file path.
+    pub params: Vec<(String, String)>,
+}
+
+tokio::task_local! {
+    static CURRENT: RequestScope;
+}
+
+/// Returns the scope of the request being handled by the current task, if any.
+pub fn current() -> Option<RequestScope> {
+    CURRENT.try_with(|scope| scope.clone()).ok()
+}
+
+/// Propagate the `X-Request-Id` of the client, or assign a new one, and echo it in the response.
+pub async fn request_id(mut req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= MAX_LENGTH && v.bytes().all(|b| b.is_ascii_graphic()))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let params = match req.extract_parts::<RawPathParams>().await {
+        Ok(params) => params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        Err(_) => vec![],
+    };
+    let scope = RequestScope { id: id.clone(), path: req.uri().path().to_string(), params };
+
+    let mut response = CURRENT.scope(scope, next.run(req)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
+}
@@ -0,0 +1,103 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::Instant;
+
+use axum::extract::{MatchedPath, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+
+/// The buckets (in seconds) of the duration histograms.
+const BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Install the Prometheus recorder once, the metrics recorded before are lost.
+///
+/// Fails if another recorder is installed already, e.g. by the application embedding this server.
+pub fn install() -> anyhow::Result<&'static PrometheusHandle> {
+    static INSTALLING: Mutex<()> = Mutex::new(());
+
+    let _installing = INSTALLING.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(handle) = HANDLE.get() {
+        return Ok(handle);
+    }
+
+    let handle = PrometheusBuilder::new().set_buckets(BUCKETS)?.install_recorder()?;
+    Ok(HANDLE.get_or_init(|| handle))
+}
+
+/// Count the HTTP requests and measure their latency, labelled by the matched route and status.
+pub async fn track(req: Request, next: Next) -> Response {
+    let method = req.method().to_string();
+    let route = match req.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => "unmatched".to_string(),
+    };
+
+    let started = Instant::now();
+    let response = next.run(req).await;
+
+    let labels = [("method", method), ("route", route), ("status", response.status().as_u16().to_string())];
+    metrics::counter!("playground_http_requests_total", &labels).increment(1);
+    metrics::histogram!("playground_http_request_duration_seconds", &labels).record(started.elapsed());
+
+    response
+}
+
+/// Measure a call to the Amphitheatre server.
+pub async fn amp<T, E>(operation: &'static str, call: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    upstream("amphitheatre", operation, call).await
+}
+
+/// Measure a call to the SCM endpoint.
+pub async fn scm<T, E>(operation: &'static str, call: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    upstream("scm", operation, call).await
+}
+
+async fn upstream<T, E>(
+    service: &'static str,
+    operation: &'static str,
+    call: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let started = Instant::now();
+    let result = call.await;
+
+    let outcome = if result.is_ok() { "success" } else { "error" };
+    metrics::counter!("playground_upstream_requests_total", "service" => service, "operation" => operation, "outcome" => outcome)
+        .increment(1);
+    metrics::histogram!("playground_upstream_request_duration_seconds", "service" => service, "operation" => operation)
+        .record(started.elapsed());
+
+    result
+}
+
+/// Gauge the streams open at the same time, the stream holds it until it's dropped.
+pub struct ActiveStream(&'static str);
+
+impl ActiveStream {
+    pub fn open(name: &'static str) -> ActiveStream {
+        metrics::gauge!(name).increment(1.0);
+        ActiveStream(name)
+    }
+}
+
+impl Drop for ActiveStream {
+    fn drop(&mut self) {
+        metrics::gauge!(self.0).decrement(1.0);
+    }
+}
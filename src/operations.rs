@@ -0,0 +1,206 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::errors::{Problem, Result};
+
+/// How long the finished operations are kept around for the clients to read them.
+const RETENTION: Duration = Duration::from_secs(3600);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// A long-running operation, e.g. provisioning a playbook.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Operation {
+    pub id: Uuid,
+    /// What the operation does, e.g. create-playbook.
+    pub kind: String,
+    pub status: OperationStatus,
+    /// A human readable description of the current step.
+    pub progress: String,
+    /// The final result once succeeded, e.g. the playbook.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    /// The problem once failed, as it would have been responded without the operation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Problem>,
+    /// When the operation was created and last updated, in seconds since the Unix epoch.
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// The user who started the operation, the only one allowed to read it.
+    #[serde(skip)]
+    pub owner: String,
+}
+
+/// Keeps track of the operations running in the background.
+#[derive(Debug, Default)]
+pub struct OperationStore {
+    operations: RwLock<HashMap<Uuid, Operation>>,
+}
+
+/// Lets a running operation report its progress.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    store: Arc<OperationStore>,
+    id: Uuid,
+}
+
+impl Progress {
+    pub fn report(&self, progress: impl Into<String>) {
+        self.store.update(self.id, |op| op.progress = progress.into());
+    }
+}
+
+impl OperationStore {
+    /// Run the task in the background as a new operation, and return the operation right away.
+    pub fn spawn<F, Fut, T>(self: &Arc<Self>, kind: &str, owner: String, task: F) -> Operation
+    where
+        F: FnOnce(Progress) -> Fut,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+        T: Serialize,
+    {
+        let now = now();
+        let operation = Operation {
+            id: Uuid::new_v4(),
+            kind: kind.to_string(),
+            status: OperationStatus::Running,
+            progress: "pending".to_string(),
+            result: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+            owner,
+        };
+
+        {
+            let mut operations = self.operations.write().unwrap();
+            operations
+                .retain(|_, op| op.status == OperationStatus::Running || now - op.updated_at < RETENTION.as_secs());
+            operations.insert(operation.id, operation.clone());
+        }
+
+        let id = operation.id;
+        let store = self.clone();
+        let future = task(Progress { store: self.clone(), id });
+        tokio::spawn(async move {
+            match future.await {
+                Ok(result) => {
+                    info!("Operation {} succeeded", id);
+                    store.update(id, |op| {
+                        op.status = OperationStatus::Succeeded;
+                        op.progress = "done".to_string();
+                        op.result = serde_json::to_value(result).ok();
+                    });
+                }
+                Err(e) => {
+                    error!("Operation {} failed: {}", id, e);
+                    store.update(id, |op| {
+                        op.status = OperationStatus::Failed;
+                        op.error = Some(e.problem());
+                    });
+                }
+            }
+        });
+
+        operation
+    }
+
+    /// Returns the operation, if the user is the one who started it.
+    pub fn get(&self, id: Uuid, user: &str) -> Option<Operation> {
+        self.operations.read().unwrap().get(&id).filter(|op| op.owner == user).cloned()
+    }
+
+    fn update(&self, id: Uuid, f: impl FnOnce(&mut Operation)) {
+        if let Some(op) = self.operations.write().unwrap().get_mut(&id) {
+            f(op);
+            op.updated_at = now();
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{ApiError, ErrorCode};
+
+    async fn finished(store: &OperationStore, id: Uuid, user: &str) -> Operation {
+        loop {
+            let op = store.get(id, user).unwrap();
+            if op.status != OperationStatus::Running {
+                return op;
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn only_the_owner_reads_the_operation() {
+        let store = Arc::new(OperationStore::default());
+        let op = store.spawn("create-playbook", "alice".to_string(), |_| async { Ok("done") });
+
+        assert!(store.get(op.id, "bob").is_none());
+        let op = finished(&store, op.id, "alice").await;
+        assert_eq!(op.status, OperationStatus::Succeeded);
+        assert_eq!(op.result, Some(serde_json::json!("done")));
+        assert!(serde_json::to_value(&op).unwrap().get("owner").is_none());
+    }
+
+    #[tokio::test]
+    async fn keeps_the_error_code_of_a_failure() {
+        let store = Arc::new(OperationStore::default());
+        let missing = Uuid::new_v4();
+        let op = store.spawn("start-playbook", "alice".to_string(), move |progress| async move {
+            progress.report("starting");
+            Err::<(), _>(ApiError::NotFoundOperation(missing))
+        });
+
+        let op = finished(&store, op.id, "alice").await;
+        assert_eq!(op.status, OperationStatus::Failed);
+        assert_eq!(op.progress, "starting");
+        let problem = op.error.unwrap();
+        assert_eq!(problem.code, ErrorCode::OperationNotFound);
+        assert_eq!(problem.status, 404);
+    }
+
+    #[tokio::test]
+    async fn forgets_the_operations_finished_long_ago() {
+        let store = Arc::new(OperationStore::default());
+        let old = store.spawn("create-playbook", "alice".to_string(), |_| async { Ok(()) });
+        finished(&store, old.id, "alice").await;
+        store.operations.write().unwrap().get_mut(&old.id).unwrap().updated_at -= RETENTION.as_secs();
+
+        let new = store.spawn("create-playbook", "alice".to_string(), |_| async { Ok(()) });
+        assert!(store.get(old.id, "alice").is_none());
+        assert!(store.get(new.id, "alice").is_some());
+    }
+}
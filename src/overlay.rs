@@ -0,0 +1,386 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use amp_common::sync::{EventKinds, Path, Synchronization};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+/// A change made to a workspace through this API.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    /// A file created or modified, with its content.
+    Write {
+        path: String,
+        #[serde(with = "crate::utils::base64")]
+        data: Vec<u8>,
+    },
+    /// An empty directory created.
+    CreateDir { path: String },
+    /// A file or directory removed.
+    Remove { path: String, directory: bool },
+    /// A file or directory moved.
+    Rename { from: String, to: String, directory: bool },
+    /// The whole workspace replaced by an archive.
+    Overwrite {
+        #[serde(with = "crate::utils::base64")]
+        data: Vec<u8>,
+    },
+}
+
+impl Change {
+    /// Build the synchronization request that replays the change on a workspace.
+    pub fn synchronization(&self) -> Synchronization {
+        let (kind, paths, payload) = match self {
+            Change::Write { path, data } => (EventKinds::Create, vec![Path::File(path.clone())], Some(data.clone())),
+            Change::CreateDir { path } => (EventKinds::Create, vec![Path::Directory(path.clone())], None),
+            Change::Remove { path, directory } => (EventKinds::Remove, vec![path_of(path, *directory)], None),
+            Change::Rename { from, to, directory } => {
+                (EventKinds::Rename, vec![path_of(from, *directory), path_of(to, *directory)], None)
+            }
+            Change::Overwrite { data } => (EventKinds::Overwrite, vec![], Some(data.clone())),
+        };
+
+        Synchronization { kind, paths, attributes: None, payload }
+    }
+}
+
+fn path_of(path: &str, directory: bool) -> Path {
+    match directory {
+        true => Path::Directory(path.to_string()),
+        false => Path::File(path.to_string()),
+    }
+}
+
+/// Returns the changes carried by a synchronization request.
+pub fn changes(req: &Synchronization) -> Vec<Change> {
+    let payload = || req.payload.clone().unwrap_or_default();
+    match req.kind {
+        EventKinds::Create | EventKinds::Modify => req
+            .paths
+            .iter()
+            .filter_map(|path| match path {
+                Path::File(path) => Some(Change::Write { path: path.clone(), data: payload() }),
+                Path::Directory(path) if req.kind == EventKinds::Create => {
+                    Some(Change::CreateDir { path: path.clone() })
+                }
+                Path::Directory(_) => None,
+            })
+            .collect(),
+        EventKinds::Remove => req
+            .paths
+            .iter()
+            .map(|path| match path {
+                Path::File(path) => Change::Remove { path: path.clone(), directory: false },
+                Path::Directory(path) => Change::Remove { path: path.clone(), directory: true },
+            })
+            .collect(),
+        EventKinds::Rename => match req.paths.as_slice() {
+            [Path::File(from), Path::File(to)] => {
+                vec![Change::Rename { from: from.clone(), to: to.clone(), directory: false }]
+            }
+            [Path::Directory(from), Path::Directory(to)] => {
+                vec![Change::Rename { from: from.clone(), to: to.clone(), directory: true }]
+            }
+            _ => vec![],
+        },
+        EventKinds::Overwrite => vec![Change::Overwrite { data: payload() }],
+    }
+}
+
+/// Apply the changes to a set of files keyed by path, e.g. the files of the upstream repository.
+/// An overwrite can't be applied, as the archive is only unpacked by the workspace.
+pub fn apply(files: &mut BTreeMap<String, Vec<u8>>, changes: &[Change]) {
+    replay(files, changes, |data| data.to_vec(), |_| None);
+}
+
+/// Returns the size (in bytes) of each file the changes leave, an overwrite counts as a single file.
+fn measure(changes: &[Change]) -> BTreeMap<String, u64> {
+    let mut sizes = BTreeMap::new();
+    replay(&mut sizes, changes, |data| data.len() as u64, |data| Some(data.len() as u64));
+    sizes
+}
+
+/// Replay the changes on the files keyed by path, keeping what `content` tells of each file,
+/// and what `archive` tells of an overwrite, if anything.
+fn replay<T>(
+    files: &mut BTreeMap<String, T>,
+    changes: &[Change],
+    content: impl Fn(&[u8]) -> T,
+    archive: impl Fn(&[u8]) -> Option<T>,
+) {
+    for change in changes {
+        match change {
+            Change::Write { path, data } => {
+                files.insert(path.clone(), content(data));
+            }
+            Change::Remove { path, directory } => {
+                files.retain(|p, _| !within(p, path, *directory));
+            }
+            Change::Rename { from, to, directory } => {
+                let moved: Vec<String> = files.keys().filter(|p| within(p, from, *directory)).cloned().collect();
+                for path in moved {
+                    if let Some(file) = files.remove(&path) {
+                        files.insert(format!("{}{}", to, &path[from.len()..]), file);
+                    }
+                }
+            }
+            Change::Overwrite { data } => {
+                if let Some(file) = archive(data) {
+                    files.clear();
+                    files.insert(String::new(), file);
+                }
+            }
+            Change::CreateDir { .. } => {}
+        }
+    }
+}
+
+/// Whether the path is the file, or inside the directory.
+fn within(path: &str, target: &str, directory: bool) -> bool {
+    match directory {
+        true => path.strip_prefix(target).is_some_and(|rest| rest.starts_with('/')),
+        false => path == target,
+    }
+}
+
+/// Append the change to the log, dropping the earlier writes of the same file it supersedes.
+fn append(log: &mut Vec<Change>, change: Change) {
+    let file = match &change {
+        Change::Overwrite { .. } => {
+            log.clear();
+            None
+        }
+        Change::Write { path, .. } | Change::Remove { path, directory: false } => Some(path.clone()),
+        _ => None,
+    };
+
+    if let Some(file) = file {
+        // Going back until a change that moves or removes more than this file.
+        let mut i = log.len();
+        while i > 0 {
+            i -= 1;
+            match &log[i] {
+                Change::Write { path, .. } | Change::Remove { path, directory: false } if *path == file => {
+                    log.remove(i);
+                }
+                Change::Rename { .. } | Change::Remove { directory: true, .. } => break,
+                _ => {}
+            }
+        }
+    }
+
+    log.push(change);
+}
+
+/// Keeps the changes made to each playbook's workspace through this API, on top of the upstream
+/// repository reference, so they can be replayed elsewhere, e.g. when forking.
+///
+/// The changes are persisted as one JSON file per playbook in the data directory, so they outlive
+/// the restarts, while only the file sizes are kept in memory for the quota checks.
+#[derive(Debug)]
+pub struct OverlayStore {
+    dir: PathBuf,
+    sizes: Mutex<HashMap<Uuid, BTreeMap<String, u64>>>,
+    /// Serializes the updates of the files.
+    writing: tokio::sync::Mutex<()>,
+}
+
+impl OverlayStore {
+    /// Open the store in the data directory, measuring the workspaces changed before a restart.
+    pub fn open(data_dir: &std::path::Path) -> anyhow::Result<OverlayStore> {
+        let dir = data_dir.join("overlays");
+        std::fs::create_dir_all(&dir)?;
+
+        let mut sizes = HashMap::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let id = path.extension().filter(|ext| *ext == "json").and(path.file_stem()).and_then(|s| s.to_str());
+            let Some(id) = id.and_then(|id| Uuid::parse_str(id).ok()) else { continue };
+            let log = std::fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(serde_json::from_slice::<Vec<Change>>(&data)?));
+            match log {
+                Ok(log) => {
+                    sizes.insert(id, measure(&log));
+                }
+                Err(e) => warn!("Ignoring the unreadable workspace changes {}: {}", path.display(), e),
+            }
+        }
+
+        Ok(OverlayStore { dir, sizes: Mutex::new(sizes), writing: tokio::sync::Mutex::new(()) })
+    }
+
+    /// Apply the changes to the overlay of the playbook's workspace.
+    pub async fn apply(&self, id: Uuid, changes: Vec<Change>) -> anyhow::Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let _writing = self.writing.lock().await;
+        let mut log = self.read(id).await?;
+        for change in changes {
+            append(&mut log, change);
+        }
+
+        // Replace the file at once, so it's never read half written.
+        let temp = self.dir.join(format!("{}.json.tmp", id));
+        tokio::fs::write(&temp, serde_json::to_vec(&log)?).await?;
+        tokio::fs::rename(&temp, self.path(id)).await?;
+        self.sizes().insert(id, measure(&log));
+
+        Ok(())
+    }
+
+    /// Returns the changes of the playbook's workspace, in the order they were made.
+    pub async fn get(&self, id: Uuid) -> anyhow::Result<Vec<Change>> {
+        self.read(id).await
+    }
+
+    /// Returns the files the changes of the playbook's workspace leave, e.g. all the files of an inline playbook.
+    pub async fn files(&self, id: Uuid) -> anyhow::Result<BTreeMap<String, Vec<u8>>> {
+        let mut files = BTreeMap::new();
+        apply(&mut files, &self.read(id).await?);
+
+        Ok(files)
+    }
+
+    /// Returns the size (in bytes) of the playbook's workspace changes, with the extra changes applied.
+    pub fn size_with(&self, id: Uuid, extra: &[Change]) -> u64 {
+        let mut sizes = self.sizes().get(&id).cloned().unwrap_or_default();
+        replay(&mut sizes, extra, |data| data.len() as u64, |data| Some(data.len() as u64));
+
+        sizes.values().sum()
+    }
+
+    /// Forget the changes of the playbook, e.g. when it's deleted.
+    pub async fn remove(&self, id: Uuid) -> anyhow::Result<()> {
+        let _writing = self.writing.lock().await;
+        match tokio::fs::remove_file(self.path(id)).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        self.sizes().remove(&id);
+
+        Ok(())
+    }
+
+    async fn read(&self, id: Uuid) -> anyhow::Result<Vec<Change>> {
+        match tokio::fs::read(self.path(id)).await {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn path(&self, id: Uuid) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// The sizes are only read and replaced at once, so they are still usable after a panic.
+    fn sizes(&self) -> MutexGuard<'_, HashMap<Uuid, BTreeMap<String, u64>>> {
+        self.sizes.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &str, data: &str) -> Change {
+        Change::Write { path: path.to_string(), data: data.as_bytes().to_vec() }
+    }
+
+    fn rename(from: &str, to: &str, directory: bool) -> Change {
+        Change::Rename { from: from.to_string(), to: to.to_string(), directory }
+    }
+
+    #[test]
+    fn converts_the_synchronization_requests() {
+        let req = Synchronization {
+            kind: EventKinds::Rename,
+            paths: vec![Path::Directory("src".to_string()), Path::Directory("lib".to_string())],
+            attributes: None,
+            payload: None,
+        };
+        assert_eq!(changes(&req), vec![rename("src", "lib", true)]);
+        assert_eq!(changes(&changes(&req)[0].synchronization()), changes(&req));
+
+        let req =
+            Synchronization { kind: EventKinds::Overwrite, paths: vec![], attributes: None, payload: Some(vec![1]) };
+        assert_eq!(changes(&req), vec![Change::Overwrite { data: vec![1] }]);
+    }
+
+    #[test]
+    fn applies_the_renames_and_removals() {
+        let mut files = BTreeMap::new();
+        let log = vec![
+            write("src/main.rs", "fn main() {}"),
+            write("src/lib.rs", ""),
+            write("srcs", "not in src"),
+            rename("src", "lib", true),
+            rename("lib/lib.rs", "lib/mod.rs", false),
+            Change::Remove { path: "srcs".to_string(), directory: false },
+        ];
+        apply(&mut files, &log);
+
+        assert_eq!(files.keys().collect::<Vec<_>>(), ["lib/main.rs", "lib/mod.rs"]);
+        assert_eq!(files["lib/main.rs"], b"fn main() {}");
+    }
+
+    #[test]
+    fn compacts_the_log() {
+        let mut log = vec![];
+        append(&mut log, write("a", "1"));
+        append(&mut log, write("a", "2"));
+        assert_eq!(log, vec![write("a", "2")]);
+
+        // A write before a rename is still needed to replay it.
+        append(&mut log, rename("a", "b", false));
+        append(&mut log, write("a", "3"));
+        assert_eq!(log.len(), 3);
+
+        append(&mut log, Change::Overwrite { data: vec![0; 4] });
+        assert_eq!(log, vec![Change::Overwrite { data: vec![0; 4] }]);
+        assert_eq!(measure(&log).values().sum::<u64>(), 4);
+    }
+
+    #[tokio::test]
+    async fn persists_the_changes() {
+        let dir = std::env::temp_dir().join(format!("overlays-{}", Uuid::new_v4()));
+        let id = Uuid::new_v4();
+
+        let store = OverlayStore::open(&dir).unwrap();
+        store.apply(id, vec![write("a", "12"), write("b", "345")]).await.unwrap();
+        store.apply(id, vec![rename("a", "c", false)]).await.unwrap();
+        assert_eq!(store.size_with(id, &[write("b", "")]), 2);
+
+        // The changes and their sizes outlive the store.
+        let store = OverlayStore::open(&dir).unwrap();
+        assert_eq!(store.size_with(id, &[]), 5);
+        assert_eq!(store.get(id).await.unwrap().len(), 3);
+
+        store.remove(id).await.unwrap();
+        assert!(store.get(id).await.unwrap().is_empty());
+        assert_eq!(store.size_with(id, &[]), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
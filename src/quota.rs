@@ -0,0 +1,393 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::errors::{ApiError, Result};
+use crate::overlay::{Change, OverlayStore};
+use crate::responses::usage::{Usage, UsageResponse};
+
+/// The resources limited per user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Resource {
+    Playbooks,
+    WorkspaceBytes,
+    RunningSeconds,
+}
+
+impl std::fmt::Display for Resource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Resource::Playbooks => write!(f, "playbooks"),
+            Resource::WorkspaceBytes => write!(f, "workspace bytes"),
+            Resource::RunningSeconds => write!(f, "running seconds"),
+        }
+    }
+}
+
+/// Tracks the usage of each user locally and enforces the configured limits, 0 means unlimited.
+///
+/// The usage is persisted as a JSON file in the data directory, so it outlives the restarts. It's
+/// small and only changes with the playbooks' lifecycle, so it's rewritten at once on each change.
+#[derive(Debug)]
+pub struct QuotaTracker {
+    playbooks: u64,
+    workspace_bytes: u64,
+    running_seconds: u64,
+    file: PathBuf,
+    state: RwLock<State>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    /// The owner of each playbook created through this API.
+    owners: HashMap<Uuid, String>,
+    /// When each running playbook was started, by the wall clock as it outlives the restarts.
+    running: HashMap<Uuid, SystemTime>,
+    /// The running time consumed by each user's stopped playbooks.
+    consumed: HashMap<String, Duration>,
+    /// The playbooks of each user being created, counted until they are created or failed to.
+    /// Not persisted, the creations in flight don't outlive a restart.
+    #[serde(skip)]
+    reserved: HashMap<String, u64>,
+}
+
+/// A playbook slot reserved for a user, given back when dropped unless the playbook was created.
+#[must_use]
+pub struct Reservation {
+    tracker: Arc<QuotaTracker>,
+    user: String,
+}
+
+impl Reservation {
+    /// Record the playbook was created in the reserved slot.
+    pub fn commit(self, id: Uuid) {
+        self.tracker.created(&self.user, id);
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut state = self.tracker.state();
+        if let Some(reserved) = state.reserved.get_mut(&self.user) {
+            *reserved -= 1;
+            if *reserved == 0 {
+                state.reserved.remove(&self.user);
+            }
+        }
+    }
+}
+
+impl QuotaTracker {
+    /// Open the tracker in the data directory, with the usage recorded before a restart.
+    pub fn open(config: &Config) -> anyhow::Result<QuotaTracker> {
+        std::fs::create_dir_all(&config.data_dir)?;
+        let file = config.data_dir.join("quotas.json");
+        let state = load(&file)?;
+
+        Ok(QuotaTracker {
+            playbooks: config.quota_playbooks,
+            workspace_bytes: config.quota_workspace_bytes,
+            running_seconds: config.quota_running_seconds,
+            file,
+            state: RwLock::new(state),
+        })
+    }
+
+    /// Reserve a slot for the user to create one more playbook, so the concurrent creations can't
+    /// both take the last one. The slot is given back if the reservation is dropped before it's committed.
+    ///
+    /// The playbook runs as soon as it's created, so the user must have running time left too.
+    pub fn reserve(self: &Arc<Self>, user: &str) -> Result<Reservation> {
+        let mut state = self.state();
+        let owned = state.owners.values().filter(|owner| *owner == user).count() as u64;
+        let used = owned + state.reserved.get(user).copied().unwrap_or_default();
+        Self::check(Resource::Playbooks, used, 1, self.playbooks)?;
+        Self::check(Resource::RunningSeconds, Self::running_time(&state, user).as_secs(), 1, self.running_seconds)?;
+        *state.reserved.entry(user.to_string()).or_default() += 1;
+
+        Ok(Reservation { tracker: self.clone(), user: user.to_string() })
+    }
+
+    /// Check the owner of the playbook has running time left to start it.
+    pub fn check_running(&self, id: Uuid) -> Result<()> {
+        let state = self.read();
+        match state.owners.get(&id) {
+            Some(user) => Self::check(
+                Resource::RunningSeconds,
+                Self::running_time(&state, user).as_secs(),
+                1,
+                self.running_seconds,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the owner of the playbook stays within the workspace size limit with the changes applied.
+    pub fn check_workspace(&self, overlays: &OverlayStore, id: Uuid, changes: &[Change]) -> Result<()> {
+        let state = self.read();
+        let Some(user) = state.owners.get(&id) else { return Ok(()) };
+
+        let used = state
+            .owners
+            .iter()
+            .filter(|(_, owner)| *owner == user)
+            .map(|(pid, _)| if *pid == id { overlays.size_with(id, changes) } else { overlays.size_with(*pid, &[]) })
+            .sum();
+        Self::check(Resource::WorkspaceBytes, used, 0, self.workspace_bytes)
+    }
+
+    /// Returns the playbooks created through this API.
+    pub fn owned(&self) -> Vec<Uuid> {
+        self.read().owners.keys().copied().collect()
+    }
+
+    /// Returns the running playbooks of the users out of running time, which are to be stopped.
+    pub fn over_running(&self) -> Vec<Uuid> {
+        if self.running_seconds == 0 {
+            return vec![];
+        }
+
+        let state = self.read();
+        state
+            .running
+            .keys()
+            .filter(|id| {
+                let user = state.owners.get(id);
+                user.is_some_and(|user| Self::running_time(&state, user).as_secs() >= self.running_seconds)
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Record a playbook created by the user.
+    fn created(&self, user: &str, id: Uuid) {
+        self.update(|state| state.owners.insert(id, user.to_string()));
+    }
+
+    /// Record the playbook was started, its running time counts from now on.
+    pub fn started(&self, id: Uuid) {
+        self.update(|state| {
+            state.running.entry(id).or_insert_with(SystemTime::now);
+        });
+    }
+
+    /// Record the playbook was stopped, its running time is added to its owner's usage.
+    pub fn stopped(&self, id: Uuid) {
+        self.update(|state| Self::stop(state, id));
+    }
+
+    /// Record the playbook was deleted.
+    pub fn deleted(&self, id: Uuid) {
+        self.update(|state| {
+            Self::stop(state, id);
+            state.owners.remove(&id);
+        });
+    }
+
+    /// Returns the current usage of the user.
+    pub fn usage(&self, overlays: &OverlayStore, user: &str) -> UsageResponse {
+        let state = self.read();
+        let owned: Vec<Uuid> = state.owners.iter().filter(|(_, owner)| *owner == user).map(|(id, _)| *id).collect();
+
+        UsageResponse {
+            user: user.to_string(),
+            playbooks: Usage { used: owned.len() as u64, limit: self.playbooks },
+            workspace_bytes: Usage {
+                used: owned.iter().map(|id| overlays.size_with(*id, &[])).sum(),
+                limit: self.workspace_bytes,
+            },
+            running_seconds: Usage { used: Self::running_time(&state, user).as_secs(), limit: self.running_seconds },
+        }
+    }
+
+    fn running_time(state: &State, user: &str) -> Duration {
+        let running: Duration = state
+            .running
+            .iter()
+            .filter(|(id, _)| state.owners.get(id).is_some_and(|owner| owner == user))
+            .map(|(_, since)| since.elapsed().unwrap_or_default())
+            .sum();
+
+        state.consumed.get(user).copied().unwrap_or_default() + running
+    }
+
+    fn stop(state: &mut State, id: Uuid) {
+        if let Some(since) = state.running.remove(&id) {
+            if let Some(user) = state.owners.get(&id).cloned() {
+                *state.consumed.entry(user).or_default() += since.elapsed().unwrap_or_default();
+            }
+        }
+    }
+
+    /// Change the usage, then persist it.
+    fn update<R>(&self, change: impl FnOnce(&mut State) -> R) -> R {
+        let mut state = self.state();
+        let result = change(&mut state);
+        if let Err(e) = save(&self.file, &state) {
+            warn!("Failed to save the usage to {}: {}", self.file.display(), e);
+        }
+
+        result
+    }
+
+    /// The usage is only changed at once, so it's still usable after a panic.
+    fn read(&self) -> RwLockReadGuard<'_, State> {
+        self.state.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn state(&self) -> RwLockWriteGuard<'_, State> {
+        self.state.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Check the usage plus the requested amount stays within the limit.
+    fn check(resource: Resource, used: u64, requested: u64, limit: u64) -> Result<()> {
+        if limit > 0 && used + requested > limit {
+            return Err(ApiError::QuotaExceeded { resource, used, limit });
+        }
+
+        Ok(())
+    }
+}
+
+/// Read the usage persisted in the file, if any.
+fn load(file: &Path) -> anyhow::Result<State> {
+    match std::fs::read(file) {
+        Ok(data) => Ok(serde_json::from_slice(&data).unwrap_or_else(|e| {
+            warn!("Ignoring the unreadable usage {}: {}", file.display(), e);
+            State::default()
+        })),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(State::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replace the file at once, so it's never read half written.
+fn save(file: &Path, state: &State) -> io::Result<()> {
+    let temp = file.with_extension("json.tmp");
+    std::fs::write(&temp, serde_json::to_vec(state)?)?;
+    std::fs::rename(&temp, file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(playbooks: u64) -> Arc<QuotaTracker> {
+        open(&std::env::temp_dir().join(format!("quotas-{}.json", Uuid::new_v4())), playbooks, 0)
+    }
+
+    fn open(file: &Path, playbooks: u64, running_seconds: u64) -> Arc<QuotaTracker> {
+        Arc::new(QuotaTracker {
+            playbooks,
+            workspace_bytes: 0,
+            running_seconds,
+            file: file.to_path_buf(),
+            state: RwLock::new(load(file).unwrap()),
+        })
+    }
+
+    #[test]
+    fn reserves_the_last_slot_once() {
+        let quotas = tracker(2);
+        quotas.reserve("alice").unwrap().commit(Uuid::new_v4());
+
+        // The creations in flight count, so only one of them gets the last slot.
+        let reservation = quotas.reserve("alice").unwrap();
+        assert!(matches!(
+            quotas.reserve("alice"),
+            Err(ApiError::QuotaExceeded { resource: Resource::Playbooks, used: 2, limit: 2 })
+        ));
+        assert!(quotas.reserve("bob").is_ok());
+
+        reservation.commit(Uuid::new_v4());
+        assert!(quotas.reserve("alice").is_err());
+        assert!(quotas.state().reserved.is_empty());
+    }
+
+    #[test]
+    fn gives_the_slot_back_if_the_creation_fails() {
+        let quotas = tracker(1);
+        let reservation = quotas.reserve("alice").unwrap();
+        assert!(quotas.reserve("alice").is_err());
+
+        drop(reservation);
+        quotas.reserve("alice").unwrap().commit(Uuid::new_v4());
+        assert!(quotas.reserve("alice").is_err());
+
+        // Deleting the playbook frees its slot.
+        let id = *quotas.state().owners.keys().next().unwrap();
+        quotas.deleted(id);
+        assert!(quotas.reserve("alice").is_ok());
+    }
+
+    #[test]
+    fn unlimited_with_zero() {
+        let quotas = tracker(0);
+        let reservations: Vec<Reservation> = (0..100).map(|_| quotas.reserve("alice").unwrap()).collect();
+        assert_eq!(reservations.len(), 100);
+    }
+
+    #[test]
+    fn persists_the_usage() {
+        let file = std::env::temp_dir().join(format!("quotas-{}.json", Uuid::new_v4()));
+        let id = Uuid::new_v4();
+        let quotas = open(&file, 1, 0);
+        quotas.reserve("alice").unwrap().commit(id);
+        quotas.started(id);
+
+        // The playbooks and their running time outlive the tracker.
+        let quotas = open(&file, 1, 0);
+        assert_eq!(quotas.owned(), [id]);
+        assert!(quotas.reserve("alice").is_err());
+        assert!(quotas.read().running.contains_key(&id));
+
+        quotas.stopped(id);
+        let quotas = open(&file, 1, 0);
+        assert!(quotas.read().running.is_empty());
+        assert!(quotas.read().consumed.contains_key("alice"));
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn stops_the_playbooks_out_of_running_time() {
+        let quotas = open(&std::env::temp_dir().join(format!("quotas-{}.json", Uuid::new_v4())), 0, 60);
+        let (id, other) = (Uuid::new_v4(), Uuid::new_v4());
+        quotas.reserve("alice").unwrap().commit(id);
+        quotas.reserve("bob").unwrap().commit(other);
+        quotas.started(id);
+        quotas.started(other);
+        assert!(quotas.over_running().is_empty());
+
+        // A playbook running for longer than its owner has left is to be stopped, and no more can be created.
+        quotas.state().running.insert(id, SystemTime::now() - Duration::from_secs(61));
+        assert_eq!(quotas.over_running(), [id]);
+        assert!(matches!(
+            quotas.reserve("alice"),
+            Err(ApiError::QuotaExceeded { resource: Resource::RunningSeconds, .. })
+        ));
+        assert!(quotas.reserve("bob").is_ok());
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::context::Context;
+use crate::errors::ApiError;
+use crate::services::PlaybookService;
+
+/// Periodically stop the idle playbooks, then delete them once they expire,
+/// and stop the running playbooks of the users out of running time.
+pub async fn run(ctx: Arc<Context>) {
+    let idle_timeout = Duration::from_secs(ctx.config.idle_timeout);
+    let ttl = Duration::from_secs(ctx.config.playbook_ttl);
+    let mut interval = tokio::time::interval(Duration::from_secs(ctx.config.reaper_interval.max(1)));
+
+    seed(&ctx);
+
+    loop {
+        interval.tick().await;
+
+        // The running time is limited whether the playbooks are used or not.
+        for id in ctx.quotas.over_running() {
+            match PlaybookService::stop(ctx.clone(), id).await {
+                Ok(_) => {
+                    ctx.activity.mark_stopped(id);
+                    info!("Stopped playbook {}, its owner is out of running time", id);
+                    metrics::counter!("playground_reaper_actions_total", "action" => "quota").increment(1);
+                }
+                Err(e) if gone(&e) => forget(&ctx, id).await,
+                Err(e) => {
+                    error!("Failed to stop playbook {} out of running time: {}", id, e);
+                    metrics::counter!("playground_reaper_failures_total", "action" => "quota").increment(1);
+                }
+            }
+        }
+
+        // Only the playbooks created through this API are reaped, not the others of the Amphitheatre
+        // server, e.g. the ones created with the CLI, even if they were read through this API.
+        let owned: HashSet<Uuid> = ctx.quotas.owned().into_iter().collect();
+        for (id, idle, stopped) in ctx.activity.idle().into_iter().filter(|(id, _, _)| owned.contains(id)) {
+            if !ttl.is_zero() && idle >= ttl {
+                match PlaybookService::delete(ctx.clone(), id).await {
+                    Ok(_) => {
+                        info!("Deleted playbook {} after being idle for {}s", id, idle.as_secs());
+                        metrics::counter!("playground_reaper_actions_total", "action" => "delete").increment(1);
+                    }
+                    Err(e) if gone(&e) => forget(&ctx, id).await,
+                    Err(e) => {
+                        error!("Failed to delete expired playbook {}: {}", id, e);
+                        metrics::counter!("playground_reaper_failures_total", "action" => "delete").increment(1);
+                    }
+                }
+            } else if !idle_timeout.is_zero() && idle >= idle_timeout && !stopped {
+                match PlaybookService::stop(ctx.clone(), id).await {
+                    Ok(_) => {
+                        ctx.activity.mark_stopped(id);
+                        info!("Stopped playbook {} after being idle for {}s", id, idle.as_secs());
+                        metrics::counter!("playground_reaper_actions_total", "action" => "stop").increment(1);
+                    }
+                    Err(e) if gone(&e) => forget(&ctx, id).await,
+                    Err(e) => {
+                        error!("Failed to stop idle playbook {}: {}", id, e);
+                        metrics::counter!("playground_reaper_failures_total", "action" => "stop").increment(1);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Track the playbooks created through this API before a restart, as if they were just used,
+/// otherwise they would never be reaped.
+fn seed(ctx: &Context) {
+    let ids = ctx.quotas.owned();
+    ids.iter().for_each(|id| ctx.activity.seed(*id));
+    info!("Tracking the activity of {} existing playbooks", ids.len());
+}
+
+/// Whether the playbook was already deleted from the Amphitheatre server, e.g. by another client.
+fn gone(e: &ApiError) -> bool {
+    matches!(e, ApiError::NotFoundPlaybook(_)) && e.status() == StatusCode::NOT_FOUND
+}
+
+/// Stop tracking a playbook that is already gone, rather than failing to reap it forever.
+async fn forget(ctx: &Context, id: Uuid) {
+    info!("Forgetting playbook {}, it was deleted from the Amphitheatre server", id);
+    PlaybookService::forget(ctx, id).await;
+    metrics::counter!("playground_reaper_actions_total", "action" => "forget").increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use amp_common::http::HTTPError;
+
+    use super::*;
+
+    #[test]
+    fn tells_a_deleted_playbook_from_the_failures() {
+        assert!(gone(&ApiError::NotFoundPlaybook(HTTPError::NotFound("gone".into()))));
+        assert!(gone(&ApiError::NotFoundPlaybook(HTTPError::Transport(404, "gone".into()))));
+        assert!(!gone(&ApiError::NotFoundPlaybook(HTTPError::Transport(503, "down".into()))));
+        assert!(!gone(&ApiError::UpstreamUnavailable { service: "amphitheatre", retry_after: 1 }));
+    }
+}
@@ -14,3 +14,4 @@
 
 pub mod file;
 pub mod playbook;
+pub mod repo;
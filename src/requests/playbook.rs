@@ -12,14 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
 pub struct CreatePlaybookRequest {
     /// Source code repository the partner should be cloned from.
     /// e.g. https://github.com/amphitheatre-app/amphitheatre.git.
-    pub repo: String,
+    /// Omit it to create the playbook from the inline `files` instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
     /// Git branch the partner should be cloned from. eg. master or main
     #[serde(skip_serializing_if = "Option::is_none")]
     pub branch: Option<String>,
@@ -31,4 +35,55 @@ pub struct CreatePlaybookRequest {
     /// are available varies by where the repo is hosted.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rev: Option<String>,
+    /// Inline source files keyed by the path relative to the workspace root,
+    /// e.g. {"src/main.rs": "fn main() {}"}, used when there is no repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<BTreeMap<String, String>>,
+    /// The language of the inline files, e.g. rust, go, node or python, also accepted as `template`.
+    /// Either one of the built-in languages or the language of a template.
+    #[serde(alias = "template", skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// The id of a template to start from, see `GET /v1/templates`.
+    /// When given, the repository and files of the template are used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template_id: Option<String>,
+    /// The title of the playbook, defaults to the repository name, or the generated
+    /// character name of the inline files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The description of the playbook, defaults to the repository description.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct UpdatePlaybookRequest {
+    /// The new title of the playbook.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The new description of the playbook.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Switch to this git branch, e.g. master or main.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Switch to this git tag, e.g. v1.0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Switch to this commit hash or named reference, e.g. 4c59b707.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// Whether to replay the files changed on the workspace on top of the new reference,
+    /// otherwise they are discarded when switching, which is the default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_changes: Option<bool>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WaitParams {
+    /// Whether to wait for the action to complete, or return `202` with an operation
+    /// to follow at `GET /v1/operations/{op}` when false.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wait: Option<bool>,
 }
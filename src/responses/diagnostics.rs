@@ -0,0 +1,36 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct GitHubDiagnosticsResponse {
+    pub tokens: Vec<TokenStatus>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TokenStatus {
+    /// The name of the token in the pool, e.g. `token-1`, or `anonymous` without a token.
+    pub name: String,
+    pub authenticated: bool,
+    /// The requests allowed per hour, unknown until GitHub has told.
+    pub limit: Option<u64>,
+    /// The requests left until the limit resets.
+    pub remaining: Option<u64>,
+    /// When (in seconds since the epoch) the limit resets.
+    pub reset: Option<u64>,
+    /// Whether the token is skipped until the limit resets.
+    pub exhausted: bool,
+}
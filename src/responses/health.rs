@@ -0,0 +1,48 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReadinessResponse {
+    /// Whether all the dependencies are reachable, and the server is not shutting down.
+    pub ready: bool,
+    /// Whether the server is shutting down, still serving for the grace period or draining the in-flight requests.
+    pub draining: bool,
+    pub dependencies: Vec<DependencyStatus>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct DependencyStatus {
+    /// The name of the dependency, e.g. `amphitheatre` or `scm`.
+    pub name: String,
+    pub healthy: bool,
+    /// Whether the dependency is reachable but limiting the calls, e.g. its rate limit is exceeded,
+    /// which doesn't make the server unready.
+    pub degraded: bool,
+    /// How long (in milliseconds) the probe took.
+    pub latency_ms: u64,
+    /// The reason why the dependency is unhealthy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct VersionResponse {
+    /// The version of the crate.
+    pub version: String,
+    /// The git SHA the binary was built from.
+    pub git_sha: String,
+}
@@ -0,0 +1,62 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use amp_common::resource::PlaybookSpec;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// The playbook updated, with the outcome of switching its workspace to the new reference.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct UpdatePlaybookResponse {
+    #[serde(flatten)]
+    pub playbook: PlaybookSpec,
+    /// What became of the workspace, only when the reference was switched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resync: Option<Resync>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Resync {
+    /// Whether the playbook was restarted on the new reference, only the running ones are,
+    /// the others get it on their next start.
+    pub restarted: bool,
+    /// Why the playbook couldn't be restarted, or its changes replayed, the update itself is done.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct ValidationReport {
+    /// Whether the playbook can be created from the repository.
+    pub valid: bool,
+    /// The name of the character declared in the manifest, if it could be parsed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub character: Option<String>,
+    /// The problems found in the request or the repository contents.
+    pub errors: Vec<ValidationError>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ValidationError {
+    /// The request field or repository path the problem relates to, e.g. branch or .amp.toml.
+    pub field: String,
+    /// A human readable description of the problem.
+    pub message: String,
+}
+
+impl ValidationError {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        ValidationError { field: field.to_string(), message: message.into() }
+    }
+}
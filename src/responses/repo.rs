@@ -0,0 +1,43 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct RepositorySearchResponse {
+    /// The total number of repositories matching the query.
+    pub total: u64,
+    /// The current page number, starting from 1.
+    pub page: u32,
+    /// The number of repositories per page.
+    pub per_page: u32,
+    /// The repositories on the current page.
+    pub items: Vec<RepositoryItem>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct RepositoryItem {
+    /// The full name of the repository, e.g. amphitheatre-app/amphitheatre.
+    pub name: String,
+    /// The description of the repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The number of stars of the repository.
+    pub stars: u64,
+    /// The default branch of the repository, e.g. master or main.
+    pub default_branch: String,
+    /// The clone URL of the repository, can be used as the `repo` to create a playbook.
+    pub url: String,
+}
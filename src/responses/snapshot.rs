@@ -0,0 +1,51 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::snapshots::Snapshot;
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct SnapshotResponse {
+    /// The short slug of the snapshot, used in the permalink.
+    pub slug: String,
+    /// The id of the playbook the snapshot was taken from.
+    pub playbook: String,
+    pub title: String,
+    /// The upstream repository the files were read from, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    /// The upstream reference the files were read from, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    /// When the snapshot was taken, in seconds since the Unix epoch.
+    pub created_at: u64,
+    /// The paths of all the files in the snapshot.
+    pub tree: Vec<String>,
+}
+
+impl From<&Snapshot> for SnapshotResponse {
+    fn from(snapshot: &Snapshot) -> Self {
+        SnapshotResponse {
+            slug: snapshot.slug.clone(),
+            playbook: snapshot.playbook.clone(),
+            title: snapshot.title.clone(),
+            repo: snapshot.repo.clone(),
+            reference: snapshot.reference.clone(),
+            created_at: snapshot.created_at,
+            tree: snapshot.tree.clone(),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct UsageResponse {
+    /// The id of the user.
+    pub user: String,
+    /// The number of playbooks owned by the user.
+    pub playbooks: Usage,
+    /// The size (in bytes) of the files written to the user's workspaces.
+    pub workspace_bytes: Usage,
+    /// The running time (in seconds) of the user's playbooks.
+    pub running_seconds: Usage,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Usage {
+    pub used: u64,
+    /// The configured limit, 0 for unlimited.
+    pub limit: u64,
+}
@@ -13,36 +13,107 @@
 // limitations under the License.
 
 use std::sync::Arc;
+use std::time::Duration;
 
-use axum::routing::{delete, get, post, put};
+use axum::extract::DefaultBodyLimit;
+use axum::middleware::from_fn_with_state;
+use axum::routing::{delete, get, patch, post, put, MethodRouter};
 use axum::Router;
 
+use crate::config::Config;
 use crate::context::Context;
-use crate::handlers::{file, folder, logger, playbook};
+use crate::handlers::{
+    self, diagnostics, event, file, folder, health, logger, metrics, operation, playbook, repo, snapshot, template,
+    usage,
+};
+use crate::middleware::timeout::timeout;
+
+pub fn build(config: &Config) -> Router<Arc<Context>> {
+    // the file contents are uploaded with a larger body, and the playbooks are provisioned with a longer timeout,
+    // while the event and log streams are left unbounded, as they last as long as the client is listening.
+    let default = Limits::new(config.max_body_size, config.request_timeout);
+    let upload = Limits::new(config.max_upload_size, config.request_timeout);
+    let long = Limits::new(config.max_body_size, config.long_request_timeout);
 
-pub fn build() -> Router<Arc<Context>> {
     Router::new()
+        // health
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/version", get(health::version))
+        .route("/metrics", get(metrics::metrics))
+        //
         // playbooks
-        .route("/v1/playbooks", post(playbook::create))
-        .route("/v1/playbooks/{id}", delete(playbook::delete))
-        .route("/v1/playbooks/{id}/actions/start", get(playbook::start))
+        .route("/v1/playbooks", long.apply(post(playbook::create)))
+        .route("/v1/playbooks/validate", default.apply(post(playbook::validate)))
+        .route("/v1/playbooks/{id}", long.apply(patch(playbook::update)))
+        .route("/v1/playbooks/{id}", default.apply(delete(playbook::delete)))
+        .route("/v1/playbooks/{id}/actions/start", long.apply(get(playbook::start)))
+        .route("/v1/playbooks/{id}/actions/fork", long.apply(post(playbook::fork)))
+        //
+        // events
+        .route("/v1/playbooks/{id}/events", get(event::events))
         //
         // logging
         .route("/v1/playbooks/{id}/logs", get(logger::logs))
         //
         // files
-        .route("/v1/playbooks/{id}/files/{path}", get(file::get))
-        .route("/v1/playbooks/{id}/files/{path}", post(file::create))
-        .route("/v1/playbooks/{id}/files/{path}", put(file::update))
-        .route("/v1/playbooks/{id}/files/{path}", delete(file::delete))
-        .route("/v1/playbooks/{id}/files/{path}/actions/copy", post(file::copy))
-        .route("/v1/playbooks/{id}/files/{path}/actions/move", post(file::rename))
+        .route("/v1/playbooks/{id}/files/{path}", default.apply(get(file::get)))
+        .route("/v1/playbooks/{id}/files/{path}", upload.apply(post(file::create)))
+        .route("/v1/playbooks/{id}/files/{path}", upload.apply(put(file::update)))
+        .route("/v1/playbooks/{id}/files/{path}", default.apply(delete(file::delete)))
+        .route("/v1/playbooks/{id}/files/{path}/actions/copy", default.apply(post(file::copy)))
+        .route("/v1/playbooks/{id}/files/{path}/actions/move", default.apply(post(file::rename)))
         //
         // folders
-        .route("/v1/playbooks/{id}/folders/{path}", get(folder::get))
-        .route("/v1/playbooks/{id}/tree", get(folder::tree))
-        .route("/v1/playbooks/{id}/folders/{path}", post(folder::create))
-        .route("/v1/playbooks/{id}/folders/{path}", delete(folder::delete))
-        .route("/v1/playbooks/{id}/folders/{path}/actions/copy", post(folder::copy))
-        .route("/v1/playbooks/{id}/folders/{path}/actions/move", post(folder::rename))
+        .route("/v1/playbooks/{id}/folders/{path}", default.apply(get(folder::get)))
+        .route("/v1/playbooks/{id}/tree", default.apply(get(folder::tree)))
+        .route("/v1/playbooks/{id}/folders/{path}", default.apply(post(folder::create)))
+        .route("/v1/playbooks/{id}/folders/{path}", default.apply(delete(folder::delete)))
+        .route("/v1/playbooks/{id}/folders/{path}/actions/copy", default.apply(post(folder::copy)))
+        .route("/v1/playbooks/{id}/folders/{path}/actions/move", default.apply(post(folder::rename)))
+        //
+        // snapshots
+        .route("/v1/playbooks/{id}/snapshots", long.apply(post(snapshot::create)))
+        .route("/v1/snapshots/{slug}", default.apply(get(snapshot::get)))
+        .route("/v1/snapshots/{slug}/files/{path}", default.apply(get(snapshot::file)))
+        //
+        // operations
+        .route("/v1/operations/{op}", default.apply(get(operation::get)))
+        //
+        // repositories
+        .route("/v1/repos/search", default.apply(get(repo::search)))
+        //
+        // templates
+        .route("/v1/templates", default.apply(get(template::list)))
+        //
+        // usage
+        .route("/v1/me/usage", default.apply(get(usage::get)))
+        //
+        // diagnostics
+        .route("/v1/diagnostics/github", default.apply(get(diagnostics::github)))
+        //
+        // and the problems for everything else
+        .fallback(handlers::not_found)
+        .method_not_allowed_fallback(handlers::method_not_allowed)
+}
+
+/// The maximum body size and the timeout of the routes.
+#[derive(Clone, Copy)]
+struct Limits {
+    body: usize,
+    timeout: Duration,
+}
+
+impl Limits {
+    fn new(body: usize, timeout: u64) -> Self {
+        Self { body, timeout: Duration::from_secs(timeout) }
+    }
+
+    /// Reject the larger bodies with 413, and the slower requests with 504.
+    fn apply<S>(self, route: MethodRouter<S>) -> MethodRouter<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        route.layer((DefaultBodyLimit::max(self.body), from_fn_with_state(self.timeout, timeout)))
+    }
 }
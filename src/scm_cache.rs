@@ -0,0 +1,231 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::Any;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use amp_common::http::HTTPError;
+use amp_common::scm::content::{Content, File};
+use amp_common::scm::errors::SCMError;
+use amp_common::scm::git::Tree;
+use axum::http::header::{ACCEPT, ETAG, IF_NONE_MATCH};
+use axum::http::StatusCode;
+use moka::future::Cache;
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::config::Config;
+use crate::errors::{ApiError, Result};
+use crate::github::GitHub;
+use crate::utils::is_missing;
+
+/// The maximum number of branch heads remembered for revalidation.
+const HEADS_CAPACITY: u64 = 10_000;
+
+/// Caches the reads from the SCM, so the popular playbooks don't exhaust the GitHub rate limit.
+///
+/// The reads are keyed by repository, commit and path, so they never go stale and are only evicted
+/// when the cache is full. A branch or tag is resolved to its commit first, trusting the last
+/// resolution for the TTL and revalidating it with `If-None-Match` after, which GitHub answers
+/// with a `304 Not Modified` that doesn't count against the rate limit.
+pub struct ScmCache {
+    ttl: Duration,
+    heads: Cache<String, Head>,
+    entries: Cache<String, Entry>,
+}
+
+/// The commit a branch or tag pointed at when it was last checked.
+#[derive(Clone, Debug)]
+struct Head {
+    sha: String,
+    etag: Option<String>,
+    checked: Instant,
+}
+
+#[derive(Clone)]
+struct Entry {
+    weight: u32,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+/// The approximate size (in bytes) a read takes in the cache.
+pub trait Weigh {
+    fn weigh(&self) -> usize;
+}
+
+impl Weigh for Content {
+    fn weigh(&self) -> usize {
+        self.path.len() + self.data.len() + self.sha.len() + self.blob_id.len()
+    }
+}
+
+impl Weigh for Vec<File> {
+    fn weigh(&self) -> usize {
+        self.iter().map(|f| f.name.len() + f.path.len() + f.sha.len() + f.blob_id.len()).sum()
+    }
+}
+
+impl Weigh for Tree {
+    fn weigh(&self) -> usize {
+        self.sha.len()
+            + self.tree.iter().map(|e| e.path.len() + e.mode.len() + e.sha.len() + e.url.len()).sum::<usize>()
+    }
+}
+
+impl ScmCache {
+    pub fn new(config: &Config) -> ScmCache {
+        ScmCache {
+            ttl: Duration::from_secs(config.scm_cache_ttl),
+            heads: Cache::builder().max_capacity(HEADS_CAPACITY).build(),
+            entries: Cache::builder()
+                .max_capacity(config.scm_cache_size)
+                .weigher(|key: &String, entry: &Entry| entry.weight.saturating_add(key.len() as u32))
+                .build(),
+        }
+    }
+
+    /// Returns the cached read of the path at the reference, or fetch it at the resolved commit.
+    ///
+    /// The `kind` tells the reads of the same path apart, e.g. a file and a folder listing.
+    pub async fn get<T, F, Fut>(
+        &self,
+        github: &GitHub,
+        kind: &'static str,
+        repo: &str,
+        reference: &str,
+        path: &str,
+        fetch: F,
+    ) -> Result<T>
+    where
+        T: Weigh + Clone + Send + Sync + 'static,
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        // Without a commit, the read can't be told apart from a later one, so it isn't cached.
+        // Counted apart from the hits and misses, which tell how well the cache does.
+        let Some(commit) = self.resolve(github, repo, reference).await? else {
+            metrics::counter!("playground_scm_cache_bypasses_total", "kind" => kind).increment(1);
+            return fetch(reference.to_string()).await;
+        };
+
+        let key = format!("{}:{}:{}:{}", kind, repo, commit, path);
+        if let Some(value) = self.entries.get(&key).await.and_then(|e| e.value.downcast_ref::<T>().cloned()) {
+            metrics::counter!("playground_scm_cache_requests_total", "kind" => kind, "result" => "hit").increment(1);
+            return Ok(value);
+        }
+        metrics::counter!("playground_scm_cache_requests_total", "kind" => kind, "result" => "miss").increment(1);
+
+        let value = fetch(commit).await?;
+        let weight = u32::try_from(value.weigh()).unwrap_or(u32::MAX);
+        self.entries.insert(key, Entry { weight, value: Arc::new(value.clone()) }).await;
+
+        Ok(value)
+    }
+
+    /// Resolve the reference to the commit it points at, none if GitHub doesn't know it as a commit,
+    /// e.g. a missing branch, which the read itself then reports.
+    ///
+    /// When GitHub fails to tell, the last commit resolved is trusted longer rather than failing.
+    async fn resolve(&self, github: &GitHub, repo: &str, reference: &str) -> Result<Option<String>> {
+        if is_commit(reference) {
+            return Ok(Some(reference.to_ascii_lowercase()));
+        }
+
+        let key = format!("{}:{}", repo, reference);
+        let cached = self.heads.get(&key).await;
+        if let Some(head) = cached.as_ref().filter(|h| h.checked.elapsed() < self.ttl) {
+            return Ok(Some(head.sha.clone()));
+        }
+
+        let mut request = github.get(&commit_path(repo, reference)).header(ACCEPT, "application/vnd.github.sha");
+        if let Some(etag) = cached.as_ref().and_then(|h| h.etag.as_ref()) {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+
+        let response = match github.send("commits.get", request).await {
+            Ok(response) => response,
+            Err(e) if is_missing(&e) || matches!(e, SCMError::ClientError(HTTPError::Transport(422, _))) => {
+                debug!("GitHub doesn't know {} of {} as a commit: {}", reference, repo, e);
+                return Ok(None);
+            }
+            Err(e) => {
+                let error = github.error(e, ApiError::FailedToReadRepo);
+                return match cached {
+                    Some(head) => {
+                        warn!("Failed to resolve {} of {}, trusting {} still: {}", reference, repo, head.sha, error);
+                        Ok(Some(head.sha))
+                    }
+                    None => Err(error),
+                };
+            }
+        };
+
+        // Not modified, the branch still points at the same commit.
+        if let (StatusCode::NOT_MODIFIED, Some(head)) = (response.status(), cached.clone()) {
+            metrics::counter!("playground_scm_cache_revalidations_total", "result" => "not_modified").increment(1);
+            let sha = head.sha.clone();
+            self.heads.insert(key, Head { checked: Instant::now(), ..head }).await;
+            return Ok(Some(sha));
+        }
+
+        let etag = response.headers().get(ETAG).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let Some(sha) = response.text().await.ok().map(|s| s.trim().to_string()).filter(|s| is_commit(s)) else {
+            debug!("GitHub didn't resolve {} of {} to a commit", reference, repo);
+            return Ok(None);
+        };
+        if cached.is_some() {
+            metrics::counter!("playground_scm_cache_revalidations_total", "result" => "modified").increment(1);
+        }
+        self.heads.insert(key, Head { sha: sha.clone(), etag, checked: Instant::now() }).await;
+
+        Ok(Some(sha))
+    }
+}
+
+/// The path of the commit the reference points at, with the repository and the reference escaped,
+/// so a branch like `feature/x` or `a#b` stays a single segment.
+fn commit_path(repo: &str, reference: &str) -> String {
+    let mut url = Url::parse("https://api.github.com/repos").expect("the base URL is valid");
+    url.path_segments_mut().expect("the base URL has a path").extend(repo.split('/')).push("commits").push(reference);
+
+    url.path().to_string()
+}
+
+/// Whether the reference is a full commit SHA, which never moves, rather than a branch or tag.
+fn is_commit(reference: &str) -> bool {
+    reference.len() == 40 && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_the_commits_from_the_branches() {
+        assert!(is_commit("0123456789abcdef0123456789abcdef01234567"));
+        assert!(is_commit("0123456789ABCDEF0123456789ABCDEF01234567"));
+        assert!(!is_commit("0123456789abcdef"));
+        assert!(!is_commit("main"));
+        assert!(!is_commit("g123456789abcdef0123456789abcdef01234567"));
+    }
+
+    #[test]
+    fn escapes_the_commit_path() {
+        assert_eq!(commit_path("tokio-rs/axum", "main"), "/repos/tokio-rs/axum/commits/main");
+        assert_eq!(commit_path("tokio-rs/axum", "feature/x"), "/repos/tokio-rs/axum/commits/feature%2Fx");
+        assert_eq!(commit_path("tokio-rs/axum", "a#b?c"), "/repos/tokio-rs/axum/commits/a%23b%3Fc");
+    }
+}
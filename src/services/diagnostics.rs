@@ -0,0 +1,51 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use futures::future;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, instrument};
+
+use crate::context::Context;
+use crate::github::GitHub;
+use crate::responses::diagnostics::GitHubDiagnosticsResponse;
+
+/// How often the rate limits are refreshed from GitHub at most, the ones known are served in between.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+pub struct DiagnosticsService;
+
+impl DiagnosticsService {
+    /// Returns the GitHub rate limit of each pooled token, as tracked from the responses of GitHub.
+    ///
+    /// They are refreshed from the `/rate_limit` API, which doesn't count against the limit itself,
+    /// at most once per `REFRESH_INTERVAL` however often they are asked for.
+    #[instrument(name = "DiagnosticsService::github", skip_all)]
+    pub async fn github(ctx: Arc<Context>) -> GitHubDiagnosticsResponse {
+        let tokens = ctx.github.all();
+        if ctx.github.claim_refresh(REFRESH_INTERVAL) {
+            future::join_all(tokens.iter().map(refresh)).await;
+        }
+
+        GitHubDiagnosticsResponse { tokens: tokens.iter().map(GitHub::status).collect() }
+    }
+}
+
+/// Refresh the rate limit of the token, keeping the last known one if GitHub can't be reached.
+async fn refresh(github: &GitHub) {
+    let request = github.get("/rate_limit").header("Accept", "application/vnd.github+json");
+    if let Err(e) = github.send("rate_limit.get", request).await {
+        debug!("Failed to refresh the GitHub rate limit: {}", e);
+    }
+}
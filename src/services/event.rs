@@ -0,0 +1,151 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use amp_common::resource::PlaybookSpec;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, instrument};
+use uuid::Uuid;
+
+use crate::context::Context;
+use crate::errors::{ApiError, Result};
+use crate::events::{EventKind, PlaybookEvent};
+use crate::services::PlaybookService;
+use crate::upstream::is_not_found;
+
+/// How often the playbook is polled for its state while being watched.
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// How many events can be queued for a watcher.
+const WATCH_BUFFER: usize = 64;
+
+pub struct EventService;
+
+impl EventService {
+    /// Watch the lifecycle events of the playbook. A reconnecting client passes the id of
+    /// the last event it has seen, and receives what it missed. The events end once the
+    /// playbook is deleted.
+    #[instrument(name = "EventService::watch", skip_all, fields(%id))]
+    pub async fn watch(
+        ctx: Arc<Context>,
+        id: Uuid,
+        last_event_id: Option<&str>,
+    ) -> Result<mpsc::Receiver<PlaybookEvent>> {
+        // Subscribe before anything is published, so no event can fall between the history and the live ones.
+        let mut receiver = ctx.events.subscribe();
+
+        let playbook = ctx.upstream.playbook(&id.to_string()).await?.map_err(ApiError::NotFoundPlaybook)?;
+        Self::observe(&ctx, id, &playbook);
+
+        // A new client only needs the current state, a reconnecting one everything since its last event.
+        let last = last_event_id.and_then(|last| ctx.events.resume_after(last));
+        let backlog = match last {
+            Some(seq) => ctx.events.since(id, seq),
+            None => ctx
+                .events
+                .since(id, 0)
+                .into_iter()
+                .rev()
+                .find(|e| e.kind != EventKind::FileSynced)
+                .into_iter()
+                .collect(),
+        };
+
+        // The playbook is polled once for all its watchers.
+        if ctx.events.watch(id) {
+            tokio::spawn(Self::poll(ctx.clone(), id));
+        }
+
+        let (sender, events) = mpsc::channel(WATCH_BUFFER);
+        tokio::spawn(async move {
+            let _watcher = Watcher { ctx: ctx.clone(), id };
+
+            let mut sent = last.unwrap_or_default();
+            for event in backlog {
+                sent = event.seq;
+                if sender.send(event).await.is_err() {
+                    return;
+                }
+            }
+
+            loop {
+                tokio::select! {
+                    received = receiver.recv() => match received {
+                        Ok(event) if event.playbook == id && event.seq > sent => {
+                            sent = event.seq;
+                            let deleted = event.kind == EventKind::Deleted;
+                            if sender.send(event).await.is_err() || deleted {
+                                return;
+                            }
+                        }
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => return,
+                    },
+                    _ = sender.closed() => return,
+                }
+            }
+        });
+
+        Ok(events)
+    }
+
+    /// Poll the playbook for its state while anybody watches it, or until it's deleted.
+    async fn poll(ctx: Arc<Context>, id: Uuid) {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            if !ctx.events.keep_polling(id) {
+                return;
+            }
+
+            // Failing to get the playbook tells nothing of its state, e.g. a slow poll or an open circuit
+            // breaker, so it's left as is until the playbook is got again.
+            match ctx.upstream.playbook(&id.to_string()).await {
+                Ok(Ok(playbook)) => Self::observe(&ctx, id, &playbook),
+                Ok(Err(e)) if is_not_found(&e) => {
+                    ctx.events.forget(id);
+                    return;
+                }
+                Ok(Err(e)) => debug!("Failed to poll playbook {}: {}", id, e),
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Derive the lifecycle state of the playbook from the one returned by the Amphitheatre server.
+    pub(crate) fn observe(ctx: &Context, id: Uuid, playbook: &PlaybookSpec) {
+        match ctx.events.state(id) {
+            // A stopped playbook stays stopped until it's started again.
+            Some(EventKind::Stopped) => {}
+            _ if PlaybookService::is_running(playbook) => ctx.events.transition(id, EventKind::Running, None),
+            _ => ctx.events.transition(id, EventKind::Building, None),
+        }
+    }
+}
+
+/// Counts a watcher of the playbook for as long as its stream lasts.
+struct Watcher {
+    ctx: Arc<Context>,
+    id: Uuid,
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.ctx.events.unwatch(self.id);
+    }
+}
@@ -13,32 +13,50 @@
 // limitations under the License.
 
 use amp_common::sync::{EventKinds, Path, Synchronization};
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tracing::debug;
+use tracing::{debug, error, instrument};
 use uuid::Uuid;
 
 use amp_common::scm::content::Content;
 
 use crate::context::Context;
 use crate::errors::{ApiError, Result};
-use crate::utils;
+use crate::events::EventKind;
+use crate::{overlay, utils};
 
 pub struct FileService;
 
 impl FileService {
-    /// Get a file content from the remote git repository.
+    /// Get a file content from the remote git repository, cached by commit,
+    /// or from the workspace changes of an inline playbook, which has no repository.
+    #[instrument(name = "FileService::get", skip_all, fields(%id))]
     pub async fn get(ctx: Arc<Context>, id: Uuid, path: String) -> Result<Content> {
-        let playbook = ctx.client.playbooks().get(&id.to_string()).await.map_err(ApiError::NotFoundPlaybook)?;
-        let source = playbook.preface.repository.unwrap();
-
-        ctx.github_client
-            .contents()
-            .find(&utils::repo(&source.repo)?, &path, &source.branch.unwrap_or_default())
+        let playbook = ctx.upstream.playbook(&id.to_string()).await?.map_err(ApiError::NotFoundPlaybook)?;
+        ctx.activity.touch(id);
+        let Some(source) = playbook.preface.repository else {
+            let data = Self::inline_files(&ctx, id)
+                .await?
+                .remove(&path)
+                .ok_or_else(|| ApiError::NotFoundContent(utils::missing(&path)))?;
+            return Ok(Content { path, data, sha: String::new(), blob_id: String::new() });
+        };
+        let reference = utils::unwrap_or_error(source.reference(), "The reference is none")?;
+
+        let repo = &utils::repo(&source.repo)?;
+        let (github, path) = (&ctx.github.take()?, &path);
+        ctx.scm_cache
+            .get(github, "contents", repo, &reference, path, |reference| async move {
+                github
+                    .call("contents.find", github.client().contents().find(repo, path, &reference))
+                    .await
+                    .map_err(|e| github.error(e, ApiError::NotFoundContent))
+            })
             .await
-            .map_err(|e| ApiError::NotFoundContent(e.to_string()))
     }
 
     /// Create a file to the workspace.
+    #[instrument(name = "FileService::create", skip_all, fields(%id))]
     pub async fn create(ctx: Arc<Context>, id: Uuid, path: String, content: String) -> Result<Content> {
         let data = content.into_bytes();
         let req = Synchronization {
@@ -79,19 +97,48 @@ impl FileService {
         todo!()
     }
 
+    /// Returns the files of an inline playbook, all made by its workspace changes.
+    pub(crate) async fn inline_files(ctx: &Context, id: Uuid) -> Result<BTreeMap<String, Vec<u8>>> {
+        ctx.overlays.files(id).await.map_err(|e| {
+            error!("Failed to read the workspace changes of playbook {}: {}", id, e);
+            ApiError::InternalServerError
+        })
+    }
+
     /// Sync to the workspace.
-    async fn sync(ctx: Arc<Context>, id: Uuid, req: Synchronization) -> Result<u16> {
-        let playbook = ctx.client.playbooks().get(&id.to_string()).await.map_err(ApiError::NotFoundPlaybook)?;
+    #[instrument(name = "FileService::sync", skip_all, fields(%id))]
+    pub(crate) async fn sync(ctx: Arc<Context>, id: Uuid, req: Synchronization) -> Result<u16> {
+        let playbook = ctx.upstream.playbook(&id.to_string()).await?.map_err(ApiError::NotFoundPlaybook)?;
+        ctx.activity.touch(id);
 
         debug!("update playbooks in {}...", id);
 
         if let Some(characters) = playbook.characters {
             let character = characters.first().unwrap();
-            ctx.client
-                .actors()
-                .sync(&id.to_string(), &character.meta.name, req)
-                .await
-                .map_err(ApiError::FailedToSynchronize)
+            let changes = overlay::changes(&req);
+            ctx.quotas.check_workspace(&ctx.overlays, id, &changes)?;
+            let paths: Vec<&str> = req
+                .paths
+                .iter()
+                .map(|path| match path {
+                    Path::File(path) | Path::Directory(path) => path.as_str(),
+                })
+                .collect();
+            let paths = paths.join(", ");
+            let status = ctx
+                .upstream
+                .call("actors.sync", ctx.upstream.client().actors().sync(&id.to_string(), &character.meta.name, req))
+                .await?
+                .map_err(ApiError::FailedToSynchronize)?;
+
+            ctx.events.publish(id, EventKind::FileSynced, Some(paths));
+
+            // Remember the changes so the workspace can be replayed elsewhere, e.g. when forking.
+            ctx.overlays.apply(id, changes).await.map_err(|e| {
+                error!("Failed to save the workspace changes of playbook {}: {}", id, e);
+                ApiError::InternalServerError
+            })?;
+            Ok(status)
         } else {
             Err(ApiError::BadPlaybook("The playbook has no characters".to_string()))
         }
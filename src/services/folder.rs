@@ -12,45 +12,96 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use uuid::Uuid;
 
 use amp_common::scm::content::{Content, File};
-use amp_common::scm::git::Tree;
+use amp_common::scm::git::{Tree, TreeEntry};
+use tracing::instrument;
 
 use crate::context::Context;
 use crate::errors::{ApiError, Result};
+use crate::services::FileService;
 use crate::utils;
-use crate::utils::unwrap_or_error;
+use crate::utils::{missing, unwrap_or_error};
 
 pub struct FolderService;
 
 impl FolderService {
+    #[instrument(name = "FolderService::get", skip_all, fields(%id))]
     pub async fn get(ctx: Arc<Context>, id: Uuid, path: String) -> Result<Vec<File>, ApiError> {
-        let playbook = ctx.client.playbooks().get(&id.to_string()).await.map_err(ApiError::NotFoundPlaybook)?;
+        let playbook = ctx.upstream.playbook(&id.to_string()).await?.map_err(ApiError::NotFoundPlaybook)?;
+        ctx.activity.touch(id);
 
-        let source = unwrap_or_error(playbook.preface.repository, "The repository is none")?;
+        // The files of an inline playbook are all in its workspace changes.
+        let Some(source) = playbook.preface.repository else {
+            let files = FileService::inline_files(&ctx, id).await?;
+            let entries = entries(&files, &path, false);
+            if entries.is_empty() && !path.trim_matches('/').is_empty() {
+                return Err(ApiError::NotFoundContent(missing(&path)));
+            }
+            return Ok(entries
+                .into_keys()
+                .map(|path| {
+                    let name = path.rsplit('/').next().unwrap_or_default().to_string();
+                    File { name, path, sha: String::new(), blob_id: String::new() }
+                })
+                .collect());
+        };
         let reference = unwrap_or_error(source.reference(), "The reference is none")?;
 
-        ctx.github_client
-            .contents()
-            .list(&utils::repo(&source.repo)?, &path, &reference)
+        let repo = &utils::repo(&source.repo)?;
+        let (github, path) = (&ctx.github.take()?, &path);
+        ctx.scm_cache
+            .get(github, "folders", repo, &reference, path, |reference| async move {
+                github
+                    .call("contents.list", github.client().contents().list(repo, path, &reference))
+                    .await
+                    .map_err(|e| github.error(e, ApiError::NotFoundContent))
+            })
             .await
-            .map_err(|e| ApiError::NotFoundContent(e.to_string()))
     }
 
+    #[instrument(name = "FolderService::tree", skip_all, fields(%id))]
     pub async fn tree(ctx: Arc<Context>, id: Uuid, recursive: Option<&String>) -> Result<Tree, ApiError> {
-        let playbook = ctx.client.playbooks().get(&id.to_string()).await.map_err(ApiError::NotFoundPlaybook)?;
+        let playbook = ctx.upstream.playbook(&id.to_string()).await?.map_err(ApiError::NotFoundPlaybook)?;
+        ctx.activity.touch(id);
+        let recursive = recursive.is_some();
 
-        let source = unwrap_or_error(playbook.preface.repository, "The repository is none")?;
+        // There is no upstream tree of an inline playbook, only its workspace changes.
+        let Some(source) = playbook.preface.repository else {
+            let files = FileService::inline_files(&ctx, id).await?;
+            let tree = entries(&files, "", recursive)
+                .into_iter()
+                .map(|(path, size)| {
+                    let (mode, kind) = if size.is_some() { ("100644", "blob") } else { ("040000", "tree") };
+                    TreeEntry {
+                        path,
+                        mode: mode.to_string(),
+                        kind: kind.to_string(),
+                        sha: String::new(),
+                        size,
+                        url: String::new(),
+                    }
+                })
+                .collect();
+            return Ok(Tree { sha: String::new(), tree, truncated: false });
+        };
         let reference = unwrap_or_error(source.reference(), "The reference is none")?;
 
-        ctx.github_client
-            .git()
-            .get_tree(&utils::repo(&source.repo)?, &reference, Option::from(recursive.is_some()))
+        let repo = &utils::repo(&source.repo)?;
+        let github = &ctx.github.take()?;
+        let kind = if recursive { "trees:recursive" } else { "trees" };
+        ctx.scm_cache
+            .get(github, kind, repo, &reference, "", |reference| async move {
+                github
+                    .call("git.get_tree", github.client().git().get_tree(repo, &reference, Some(recursive)))
+                    .await
+                    .map_err(|e| github.error(e, ApiError::NotFoundFolder))?
+                    .ok_or_else(|| ApiError::NotFoundFolder(missing("The folder is none")))
+            })
             .await
-            .map_err(|e| ApiError::NotFoundFolder(e.to_string()))?
-            .ok_or(ApiError::NotFoundFolder("The folder is none".to_string()))
     }
 
     pub async fn create(_ctx: Arc<Context>, _id: Uuid, _path: String) -> Result<Content> {
@@ -69,3 +120,50 @@ impl FolderService {
         todo!()
     }
 }
+
+/// Returns the entries of the folder among the files, with the size of the files, none for the directories:
+/// only the ones right under it, or all the ones within it when recursive.
+fn entries(files: &BTreeMap<String, Vec<u8>>, folder: &str, recursive: bool) -> BTreeMap<String, Option<u64>> {
+    let folder = folder.trim_matches('/');
+    let mut entries = BTreeMap::new();
+    for (path, data) in files {
+        let rest = match folder.is_empty() {
+            true => Some(path.as_str()),
+            false => path.strip_prefix(folder).and_then(|rest| rest.strip_prefix('/')),
+        };
+        let Some(rest) = rest else { continue };
+
+        // The directories on the way to the file are entries too.
+        let base = path.len() - rest.len();
+        for (i, _) in rest.match_indices('/') {
+            entries.insert(path[..base + i].to_string(), None);
+            if !recursive {
+                break;
+            }
+        }
+        if recursive || !rest.contains('/') {
+            entries.insert(path.clone(), Some(data.len() as u64));
+        }
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_the_entries_of_the_inline_files() {
+        let files: BTreeMap<String, Vec<u8>> =
+            ["main.rs", "src/lib.rs", "src/a/b.rs", "srcs"].iter().map(|p| (p.to_string(), vec![0; 2])).collect();
+        let paths = |folder, recursive| entries(&files, folder, recursive).into_keys().collect::<Vec<_>>();
+
+        assert_eq!(paths("", false), ["main.rs", "src", "srcs"]);
+        assert_eq!(paths("/src/", false), ["src/a", "src/lib.rs"]);
+        assert_eq!(paths("", true), ["main.rs", "src", "src/a", "src/a/b.rs", "src/lib.rs", "srcs"]);
+        assert_eq!(entries(&files, "src", true)["src/a"], None);
+        assert_eq!(entries(&files, "src", true)["src/lib.rs"], Some(2));
+        assert!(paths("main", false).is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use amp_common::http::HTTPError;
+use amp_common::scm::errors::SCMError;
+use tokio::sync::Mutex;
+use tracing::instrument;
+
+use crate::context::Context;
+use crate::responses::health::{DependencyStatus, ReadinessResponse, VersionResponse};
+
+pub struct HealthService;
+
+/// The dependencies last probed and when, reused by the next readiness probes for a while.
+#[derive(Default)]
+pub struct ReadinessCache {
+    last: Mutex<Option<(Instant, Vec<DependencyStatus>)>>,
+}
+
+impl HealthService {
+    /// Probes the Amphitheatre server and the SCM endpoint concurrently, unless the server is draining.
+    ///
+    /// The dependencies probed are reused for `readiness_cache_ttl` seconds, and the concurrent probes
+    /// wait for the same one, so the dependencies are called once per period however often it's probed.
+    #[instrument(name = "HealthService::ready", skip_all)]
+    pub async fn ready(ctx: Arc<Context>) -> ReadinessResponse {
+        // No new traffic should be routed to a server shutting down, whatever its dependencies say.
+        if ctx.shutdown.is_stopping() {
+            return ReadinessResponse { ready: false, draining: true, dependencies: vec![] };
+        }
+
+        let mut last = ctx.readiness.last.lock().await;
+        let ttl = Duration::from_secs(ctx.config.readiness_cache_ttl);
+        let dependencies = match last.as_ref() {
+            Some((probed, dependencies)) if probed.elapsed() < ttl => dependencies.clone(),
+            _ => {
+                let dependencies = Self::probe(&ctx).await;
+                *last = Some((Instant::now(), dependencies.clone()));
+                dependencies
+            }
+        };
+
+        ReadinessResponse { ready: dependencies.iter().all(|d| d.healthy), draining: false, dependencies }
+    }
+
+    async fn probe(ctx: &Context) -> Vec<DependencyStatus> {
+        let timeout = Duration::from_secs(ctx.config.readiness_timeout);
+
+        let (amphitheatre, scm) = tokio::join!(
+            probe("amphitheatre", timeout, async {
+                match ctx.upstream.call("playbooks.list", ctx.upstream.client().playbooks().list(None)).await {
+                    Ok(Ok(_)) => Ok(()),
+                    Ok(Err(e)) => Err(Failure::Unhealthy(e.to_string())),
+                    Err(e) => Err(Failure::Unhealthy(e.to_string())),
+                }
+            }),
+            // The rate limit endpoint is authenticated with the pooled tokens, and doesn't count against it.
+            probe("scm", timeout, async {
+                let github = ctx.github.take().map_err(|e| Failure::Degraded(e.to_string()))?;
+                match github.send("health.rate_limit", github.get("/rate_limit")).await {
+                    Ok(_) => Ok(()),
+                    Err(e @ SCMError::ClientError(HTTPError::Transport(403 | 429, _))) => {
+                        Err(Failure::Degraded(e.to_string()))
+                    }
+                    Err(e) => Err(Failure::Unhealthy(e.to_string())),
+                }
+            }),
+        );
+
+        vec![amphitheatre, scm]
+    }
+
+    /// Returns the crate version and the git SHA of the build.
+    pub fn version() -> VersionResponse {
+        VersionResponse { version: env!("CARGO_PKG_VERSION").to_string(), git_sha: env!("GIT_SHA").to_string() }
+    }
+}
+
+/// Why a dependency failed its probe.
+enum Failure {
+    /// The dependency is reachable, but limiting the calls.
+    Degraded(String),
+    Unhealthy(String),
+}
+
+async fn probe<F>(name: &str, timeout: Duration, check: F) -> DependencyStatus
+where
+    F: Future<Output = Result<(), Failure>>,
+{
+    let started = Instant::now();
+    let result = match tokio::time::timeout(timeout, check).await {
+        Ok(result) => result,
+        Err(_) => Err(Failure::Unhealthy(format!("Timed out after {}s", timeout.as_secs()))),
+    };
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    let (healthy, degraded, message) = match result {
+        Ok(()) => (true, false, None),
+        Err(Failure::Degraded(message)) => {
+            tracing::warn!("Readiness probe of {} is degraded: {}", name, message);
+            (true, true, Some(message))
+        }
+        Err(Failure::Unhealthy(message)) => {
+            tracing::warn!("Readiness probe of {} failed: {}", name, message);
+            (false, false, Some(message))
+        }
+    };
+
+    DependencyStatus { name: name.to_string(), healthy, degraded, latency_ms, message }
+}
@@ -14,6 +14,7 @@
 
 use reqwest_eventsource::EventSource;
 use std::sync::Arc;
+use tracing::instrument;
 use uuid::Uuid;
 
 use crate::context::Context;
@@ -22,12 +23,14 @@ use crate::errors::ApiError;
 pub struct LoggerService;
 
 impl LoggerService {
+    #[instrument(name = "LoggerService::logs", skip_all, fields(%id))]
     pub async fn logs(ctx: Arc<Context>, id: Uuid) -> Result<EventSource, ApiError> {
-        let playbook = ctx.client.playbooks().get(&id.to_string()).await.map_err(ApiError::NotFoundPlaybook)?;
+        let playbook = ctx.upstream.playbook(&id.to_string()).await?.map_err(ApiError::NotFoundPlaybook)?;
+        ctx.activity.touch(id);
 
         if let Some(characters) = playbook.characters {
             let character = characters.first().unwrap();
-            Ok(ctx.client.actors().logs(&id.to_string(), &character.meta.name))
+            Ok(ctx.upstream.client().actors().logs(&id.to_string(), &character.meta.name))
         } else {
             Err(ApiError::BadPlaybook("The playbook has no characters".to_string()))
         }
@@ -12,14 +12,38 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod diagnostics;
+pub use diagnostics::DiagnosticsService;
+
+mod event;
+pub use event::EventService;
+
 mod file;
 pub use file::FileService;
 
 mod folder;
 pub use folder::FolderService;
 
+mod health;
+pub use health::{HealthService, ReadinessCache};
+
 mod logger;
 pub use logger::LoggerService;
 
+mod operation;
+pub use operation::OperationService;
+
 mod playbook;
 pub use playbook::PlaybookService;
+
+mod repo;
+pub use repo::RepoService;
+
+mod template;
+pub use template::TemplateService;
+
+mod snapshot;
+pub use snapshot::SnapshotService;
+
+mod usage;
+pub use usage::UsageService;
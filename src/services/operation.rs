@@ -0,0 +1,70 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::context::Context;
+use crate::errors::{ApiError, Result};
+use crate::operations::Operation;
+use crate::requests::playbook::CreatePlaybookRequest;
+use crate::services::PlaybookService;
+
+pub struct OperationService;
+
+impl OperationService {
+    /// Create a playbook and wait for it to be running, in the background.
+    #[instrument(name = "OperationService::create", skip_all, fields(%user))]
+    pub fn create(ctx: Arc<Context>, req: CreatePlaybookRequest, user: String) -> Operation {
+        ctx.operations.clone().spawn("create-playbook", user.clone(), move |progress| async move {
+            progress.report("creating");
+            let playbook = PlaybookService::create(ctx.clone(), &req, &user).await?;
+
+            progress.report("provisioning");
+            PlaybookService::wait(ctx, &playbook.id).await
+        })
+    }
+
+    /// Start a playbook and wait for it to be running, in the background.
+    #[instrument(name = "OperationService::start", skip_all, fields(%id, %user))]
+    pub fn start(ctx: Arc<Context>, id: Uuid, user: &str) -> Operation {
+        ctx.operations.clone().spawn("start-playbook", user.to_string(), move |progress| async move {
+            progress.report("starting");
+            PlaybookService::start(ctx.clone(), id).await?;
+
+            progress.report("provisioning");
+            PlaybookService::wait(ctx, &id.to_string()).await
+        })
+    }
+
+    /// Fork a playbook and wait for the fork to be running, in the background.
+    #[instrument(name = "OperationService::fork", skip_all, fields(%id, %user))]
+    pub fn fork(ctx: Arc<Context>, id: Uuid, user: String) -> Operation {
+        ctx.operations.clone().spawn("fork-playbook", user.clone(), move |progress| async move {
+            progress.report("forking");
+            let playbook = PlaybookService::fork(ctx.clone(), id, &user).await?;
+
+            progress.report("provisioning");
+            PlaybookService::wait(ctx, &playbook.id).await
+        })
+    }
+
+    /// Returns the operation of the user with its progress, and the result or error once finished.
+    /// The operations of the other users are not found, rather than telling they exist.
+    #[instrument(name = "OperationService::get", skip_all, fields(%id, %user))]
+    pub async fn get(ctx: Arc<Context>, id: Uuid, user: &str) -> Result<Operation> {
+        ctx.operations.get(id, user).ok_or(ApiError::NotFoundOperation(id))
+    }
+}
@@ -14,48 +14,477 @@
 
 use amp_client::playbooks::PlaybookPayload;
 use amp_common::resource::{PlaybookSpec, Preface};
-use amp_common::schema::GitReference;
+use amp_common::schema::{Character, GitReference};
+use axum::http::StatusCode;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tracing::{error, info};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, instrument, warn};
 
 use uuid::Uuid;
 
 use crate::context::Context;
 use crate::errors::ApiError;
 use crate::errors::Result;
-use crate::requests::playbook::CreatePlaybookRequest;
-use crate::utils::{repo, unwrap_or_error};
+use crate::events::EventKind;
+use crate::overlay::Change;
+use crate::requests::playbook::{CreatePlaybookRequest, UpdatePlaybookRequest};
+use crate::responses::playbook::{Resync, UpdatePlaybookResponse, ValidationError, ValidationReport};
+use crate::services::{EventService, FileService, TemplateService};
+use crate::templates::TemplateRegistry;
+use crate::upstream::{failing, is_not_found};
+use crate::utils::{is_missing, repo, unwrap_or_error};
+
+/// The character manifest file expected at the root of the repository.
+const MANIFEST_FILE: &str = ".amp.toml";
+
+/// The prefix of the generated character name of the inline playbooks, followed by the language.
+const INLINE_PREFIX: &str = "playground-";
+
+/// The version of the generated character of the inline playbooks.
+const INLINE_VERSION: &str = "0.0.1";
+
+/// The language assumed for inline files when none is given.
+const DEFAULT_LANGUAGE: &str = "generic";
+
+/// The languages of the inline files, besides the ones of the templates.
+const LANGUAGES: &[&str] =
+    &[DEFAULT_LANGUAGE, "c", "cpp", "dotnet", "go", "java", "node", "php", "python", "ruby", "rust"];
+
+/// How long to wait between two attempts to push the inline files.
+const SYNC_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the playbook is polled while waiting for it to be running.
+const PROVISION_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// The character manifest generated for the inline playbooks.
+#[derive(Serialize)]
+struct InlineManifest<'a> {
+    name: &'a str,
+    version: &'a str,
+    description: &'a str,
+}
 
 pub struct PlaybookService;
 
 impl PlaybookService {
-    pub async fn create(ctx: Arc<Context>, req: &CreatePlaybookRequest) -> Result<PlaybookSpec> {
-        let repo = repo(&req.repo)?;
+    #[instrument(name = "PlaybookService::create", skip_all, fields(%user))]
+    pub async fn create(ctx: Arc<Context>, req: &CreatePlaybookRequest, user: &str) -> Result<PlaybookSpec> {
+        let req = Self::resolve(&ctx, req)?;
+
+        let mut changes = vec![];
+        if let Some(files) = &req.files {
+            let errors = Self::check_files(files);
+            if !errors.is_empty() {
+                return Err(ApiError::InvalidPlaybook(errors));
+            }
+            changes = files
+                .iter()
+                .map(|(path, content)| Change::Write { path: path.clone(), data: content.clone().into_bytes() })
+                .collect();
+        }
+
+        Self::create_with(ctx, &req, changes, user).await
+    }
+
+    /// Create the playbook from the repository of the request, or with a generated character when
+    /// there is none, then push the changes to its workspace.
+    async fn create_with(
+        ctx: Arc<Context>,
+        req: &CreatePlaybookRequest,
+        changes: Vec<Change>,
+        user: &str,
+    ) -> Result<PlaybookSpec> {
+        let reservation = ctx.quotas.reserve(user)?;
+
+        let mut payload = match &req.repo {
+            Some(source) => Self::from_repo(&ctx, req, source).await?,
+            None => Self::inline(&ctx, req.language.as_deref())?,
+        };
+        if let Some(title) = &req.title {
+            payload.title = title.clone();
+        }
+        if let Some(description) = &req.description {
+            payload.description = description.clone();
+        }
+
+        let playbook = ctx
+            .upstream
+            .call("playbooks.create", ctx.upstream.client().playbooks().create(payload))
+            .await?
+            .map_err(ApiError::FailedToCreatePlaybook)?;
+        let id = Self::id(&playbook)?;
+        ctx.activity.touch(id);
+        reservation.commit(id);
+        // The playbook runs as soon as it's created, its running time counts from now on.
+        ctx.quotas.started(id);
+        ctx.events.transition(id, EventKind::Created, None);
+
+        // The changes can only be pushed to the workspace once the actor is up, and the playbook
+        // is useless without them, so it's not left behind if they can't be pushed.
+        if let Err(e) = Self::push(&ctx, id, &changes).await {
+            if let Err(e) = Self::delete(ctx.clone(), id).await {
+                warn!("Failed to delete playbook {} after failing to push its files: {}", id, e);
+            }
+            return Err(e);
+        }
+
+        Ok(playbook)
+    }
+
+    /// Build the playbook of the repository, checking it has a valid character manifest at the reference.
+    async fn from_repo(ctx: &Context, req: &CreatePlaybookRequest, source: &str) -> Result<PlaybookPayload> {
+        let repo = repo(source)?;
         let name = unwrap_or_error(repo.split('/').nth(1), "The repo name is None")?.to_string();
-        let repository = ctx.github_client.repositories().find(&repo).await.map_err(ApiError::NotFoundRepo)?;
+        let github = ctx.github.take()?;
+        let repository = github
+            .call("repositories.find", github.client().repositories().find(&repo))
+            .await
+            .map_err(|e| github.error(e, ApiError::NotFoundRepo))?;
         let description = repository.and_then(|r| r.description).unwrap_or_default();
         let repository = GitReference {
-            repo: req.repo.clone(),
+            repo: source.to_string(),
             branch: req.branch.clone(),
             tag: req.tag.clone(),
             rev: req.rev.clone(),
             ..GitReference::default()
         };
-        if repository.reference().is_none() {
+        let Some(reference) = repository.reference() else {
             return Err(ApiError::BadPlaybookRequest("Requires either branch, tag or rev".to_string()));
+        };
+
+        // Make sure the repository has a valid character manifest before creating the playbook,
+        // otherwise it would only fail later when the playbook is used.
+        let report = Self::inspect(ctx, &repo, &reference).await?;
+        if !report.valid {
+            return Err(ApiError::InvalidPlaybook(report.errors));
         }
+
         let preface = Preface { name: Some(name), repository: Some(repository), ..Preface::default() };
-        let payload = PlaybookPayload { title: repo, description, preface };
+        Ok(PlaybookPayload { title: repo, description, preface })
+    }
+
+    /// Build a playbook of inline files, with a generated character instead of a repository.
+    fn inline(ctx: &Context, language: Option<&str>) -> Result<PlaybookPayload> {
+        let language = Self::check_language(&ctx.templates, language)?;
+        let name = format!("{}{}-{}", INLINE_PREFIX, language, &Uuid::new_v4().simple().to_string()[..8]);
+        let description = format!("Inline {} playbook", language);
+        let manifest = Self::manifest(&name, &description)?;
+
+        let preface = Preface { name: Some(name.clone()), manifest: Some(manifest), ..Preface::default() };
+        Ok(PlaybookPayload { title: name, description, preface })
+    }
+
+    /// Build the character manifest of an inline playbook, escaping the values as TOML does.
+    fn manifest(name: &str, description: &str) -> Result<String> {
+        let manifest = InlineManifest { name, version: INLINE_VERSION, description };
+        toml::to_string(&manifest).map_err(|e| {
+            error!("Failed to build the manifest of the inline playbook {}: {}", name, e);
+            ApiError::InternalServerError
+        })
+    }
+
+    /// Returns the language of an inline playbook from its generated character name.
+    fn inline_language(name: &str) -> Option<&str> {
+        name.strip_prefix(INLINE_PREFIX)?.rsplit_once('-').map(|(language, _)| language)
+    }
+
+    /// Wait for the actor of the playbook to be up, then push the changes to its workspace,
+    /// giving up after the provision timeout.
+    #[instrument(name = "PlaybookService::push", skip_all, fields(%id))]
+    async fn push(ctx: &Arc<Context>, id: Uuid, changes: &[Change]) -> Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(ctx.config.provision_timeout);
+        let mut pending = changes.iter();
+        let mut current = pending.next();
+
+        for attempt in 1.. {
+            while let Some(change) = current {
+                match FileService::sync(ctx.clone(), id, change.synchronization()).await {
+                    Ok(_) => {}
+                    Err(e @ ApiError::QuotaExceeded { .. }) => return Err(e),
+                    Err(e) if Instant::now() >= deadline => {
+                        return Err(ApiError::FailedToProvision(format!(
+                            "Gave up pushing the files to playbook {}, the actor is not ready: {}",
+                            id, e
+                        )));
+                    }
+                    Err(e) => {
+                        debug!("The actor of playbook {} is not ready yet (attempt {}): {}", id, attempt, e);
+                        break;
+                    }
+                }
+                current = pending.next();
+            }
+
+            if current.is_none() {
+                break;
+            }
+            tokio::time::sleep(SYNC_RETRY_INTERVAL).await;
+        }
 
-        ctx.client.playbooks().create(payload).await.map_err(ApiError::FailedToCreatePlaybook)
+        info!("Pushed the files to playbook {}", id);
+        Ok(())
     }
 
+    /// Resolve the template of the request if any, and check the request has a single source.
+    fn resolve<'a>(ctx: &Context, req: &'a CreatePlaybookRequest) -> Result<Cow<'a, CreatePlaybookRequest>> {
+        // A template stands for a repository and reference, or a set of inline files.
+        let req = match &req.template_id {
+            Some(id) => Cow::Owned(TemplateService::resolve(ctx, id, req)?),
+            None => Cow::Borrowed(req),
+        };
+
+        if req.repo.is_some() && req.files.is_some() {
+            return Err(ApiError::BadPlaybookRequest("Requires either repo or files, not both".to_string()));
+        }
+
+        Ok(req)
+    }
+
+    /// Check the language of the inline files is a known one, returns it in lower case.
+    fn check_language(templates: &TemplateRegistry, language: Option<&str>) -> Result<String> {
+        let language = language.unwrap_or(DEFAULT_LANGUAGE).trim().to_lowercase();
+        let templates = templates.list().iter().filter_map(|t| t.language.as_deref());
+        if !LANGUAGES.iter().copied().chain(templates).any(|l| l.eq_ignore_ascii_case(&language)) {
+            return Err(ApiError::BadPlaybookRequest(format!(
+                "Unsupported language {:?}, expected one of {}",
+                language,
+                LANGUAGES.join(", ")
+            )));
+        }
+
+        Ok(language)
+    }
+
+    /// Check the inline files are not empty and all live inside the workspace.
+    fn check_files(files: &BTreeMap<String, String>) -> Vec<ValidationError> {
+        if files.is_empty() {
+            return vec![ValidationError::new("files", "Requires at least one file")];
+        }
+
+        files
+            .keys()
+            .filter(|path| path.is_empty() || path.starts_with('/') || path.split('/').any(|p| p == ".."))
+            .map(|path| ValidationError::new("files", format!("Invalid file path: {:?}", path)))
+            .collect()
+    }
+
+    /// Validate the repository contents without creating the playbook.
+    #[instrument(name = "PlaybookService::validate", skip_all)]
+    pub async fn validate(ctx: Arc<Context>, req: &CreatePlaybookRequest) -> Result<ValidationReport> {
+        let req = Self::resolve(&ctx, req)?;
+        if let Some(files) = &req.files {
+            Self::check_language(&ctx.templates, req.language.as_deref())?;
+            let errors = Self::check_files(files);
+            return Ok(ValidationReport { valid: errors.is_empty(), character: None, errors });
+        }
+
+        let source = unwrap_or_error(req.repo.as_ref(), "Requires either repo or files")?;
+        let repo = repo(source)?;
+        let repository = GitReference {
+            repo: source.clone(),
+            branch: req.branch.clone(),
+            tag: req.tag.clone(),
+            rev: req.rev.clone(),
+            ..GitReference::default()
+        };
+        let Some(reference) = repository.reference() else {
+            let error = ValidationError::new("reference", "Requires either branch, tag or rev");
+            return Ok(ValidationReport { valid: false, character: None, errors: vec![error] });
+        };
+
+        let github = ctx.github.take()?;
+        if github
+            .call("repositories.find", github.client().repositories().find(&repo))
+            .await
+            .map_err(|e| github.error(e, ApiError::NotFoundRepo))?
+            .is_none()
+        {
+            let error = ValidationError::new("repo", format!("The repository {} does not exist", repo));
+            return Ok(ValidationReport { valid: false, character: None, errors: vec![error] });
+        }
+
+        Self::inspect(&ctx, &repo, &reference).await
+    }
+
+    /// Fetch and parse the character manifest of the repository at the given reference.
+    ///
+    /// Only a manifest the SCM says is not there is reported as missing, the other errors are
+    /// returned as they are, e.g. the exceeded rate limit or an unreachable GitHub, as the manifest
+    /// may well be there.
+    async fn inspect(ctx: &Context, repo: &str, reference: &str) -> Result<ValidationReport> {
+        debug!("inspect the manifest of {} at {}...", repo, reference);
+
+        let github = ctx.github.take()?;
+        match github.call("contents.find", github.client().contents().find(repo, MANIFEST_FILE, reference)).await {
+            Ok(content) => Ok(Self::check_manifest(content.data)),
+            Err(e) if is_missing(&e) => {
+                let message = format!("The repository has no {} at {}", MANIFEST_FILE, reference);
+                Ok(ValidationReport {
+                    valid: false,
+                    character: None,
+                    errors: vec![ValidationError::new(MANIFEST_FILE, message)],
+                })
+            }
+            Err(e) => Err(github.error(e, ApiError::NotFoundContent)),
+        }
+    }
+
+    /// Parse the character manifest, reporting what's wrong with it.
+    fn check_manifest(data: Vec<u8>) -> ValidationReport {
+        let mut errors = vec![];
+        let character = match String::from_utf8(data) {
+            Ok(manifest) => match toml::from_str::<Character>(&manifest) {
+                Ok(character) => Some(character.meta.name),
+                Err(e) => {
+                    errors.push(ValidationError::new(MANIFEST_FILE, format!("Invalid manifest: {}", e.message())));
+                    None
+                }
+            },
+            Err(e) => {
+                errors.push(ValidationError::new(MANIFEST_FILE, format!("The manifest is not valid UTF-8: {}", e)));
+                None
+            }
+        };
+        if character.as_ref().is_some_and(|name| name.trim().is_empty()) {
+            errors.push(ValidationError::new(MANIFEST_FILE, "The character name is empty"));
+        }
+
+        ValidationReport { valid: errors.is_empty(), character, errors }
+    }
+
+    /// Fork a playbook: create a new one from the same source, and replay the changes
+    /// made on the source workspace into it once its actor is up.
+    #[instrument(name = "PlaybookService::fork", skip_all, fields(%id, %user))]
+    pub async fn fork(ctx: Arc<Context>, id: Uuid, user: &str) -> Result<PlaybookSpec> {
+        let source = ctx.upstream.playbook(&id.to_string()).await?.map_err(ApiError::NotFoundPlaybook)?;
+        let changes = ctx.overlays.get(id).await.map_err(|e| {
+            error!("Failed to read the workspace changes of playbook {}: {}", id, e);
+            ApiError::InternalServerError
+        })?;
+
+        info!("Fork playbooks in {}...", id);
+        let mut req = CreatePlaybookRequest {
+            title: Some(source.title),
+            description: source.description,
+            ..CreatePlaybookRequest::default()
+        };
+        match source.preface.repository {
+            Some(repository) => {
+                req.repo = Some(repository.repo);
+                req.branch = repository.branch;
+                req.tag = repository.tag;
+                req.rev = repository.rev;
+            }
+            // The files of an inline playbook are all in its workspace changes.
+            None => req.language = source.preface.name.as_deref().and_then(Self::inline_language).map(String::from),
+        }
+
+        Self::create_with(ctx, &req, changes, user).await
+    }
+
+    /// Update the title and description of the playbook, or switch its git reference.
+    ///
+    /// Switching restarts the running playbook on the new reference, the files changed on its workspace
+    /// are discarded unless asked to replay them. Failing to restart it doesn't fail the update,
+    /// which is done by then, it's reported instead.
+    #[instrument(name = "PlaybookService::update", skip_all, fields(%id))]
+    pub async fn update(ctx: Arc<Context>, id: Uuid, req: &UpdatePlaybookRequest) -> Result<UpdatePlaybookResponse> {
+        let playbook = ctx.upstream.playbook(&id.to_string()).await?.map_err(ApiError::NotFoundPlaybook)?;
+        ctx.activity.touch(id);
+
+        let mut preface = playbook.preface;
+        let switch = req.branch.is_some() || req.tag.is_some() || req.rev.is_some();
+        if switch {
+            let source = unwrap_or_error(preface.repository.take(), "The playbook has no repository to switch")?;
+            let repository =
+                GitReference { branch: req.branch.clone(), tag: req.tag.clone(), rev: req.rev.clone(), ..source };
+            let reference = unwrap_or_error(repository.reference(), "The reference is none")?;
+
+            // The new reference must be as usable as the one the playbook was created from.
+            let report = Self::inspect(&ctx, &repo(&repository.repo)?, &reference).await?;
+            if !report.valid {
+                return Err(ApiError::InvalidPlaybook(report.errors));
+            }
+            preface.repository = Some(repository);
+        }
+
+        info!("Update playbooks in {}...", id);
+        let payload = PlaybookPayload {
+            title: req.title.clone().unwrap_or(playbook.title),
+            description: req.description.clone().or(playbook.description).unwrap_or_default(),
+            preface,
+        };
+        let playbook = ctx
+            .upstream
+            .call("playbooks.update", ctx.upstream.client().playbooks().update(&id.to_string(), payload))
+            .await?
+            .map_err(ApiError::FailedToUpdatePlaybook)?;
+
+        let resync = match switch {
+            // Told by the Amphitheatre server, this server may well not have observed it, e.g. after a restart.
+            true => Some(Self::resync(&ctx, id, Self::is_running(&playbook), req.keep_changes.unwrap_or(false)).await),
+            false => None,
+        };
+
+        Ok(UpdatePlaybookResponse { playbook, resync })
+    }
+
+    /// Switch the workspace to the new reference: discard or keep its changes, and restart the
+    /// playbook if it's running, so the workspace is cloned again, then replay the changes kept.
+    async fn resync(ctx: &Arc<Context>, id: Uuid, running: bool, keep_changes: bool) -> Resync {
+        // The changes made on the old reference may well not apply to the new one.
+        if !keep_changes {
+            if let Err(e) = ctx.overlays.remove(id).await {
+                warn!("Failed to discard the workspace changes of playbook {}: {}", id, e);
+            }
+        }
+
+        if !running {
+            return Resync { restarted: false, error: None };
+        }
+
+        info!("Resync playbooks in {}...", id);
+        let restarted = async {
+            Self::stop(ctx.clone(), id).await?;
+            Self::start(ctx.clone(), id).await?;
+            let changes = ctx.overlays.get(id).await.map_err(|e| {
+                error!("Failed to read the workspace changes of playbook {}: {}", id, e);
+                ApiError::InternalServerError
+            })?;
+            Self::push(ctx, id, &changes).await
+        };
+
+        match restarted.await {
+            Ok(()) => Resync { restarted: true, error: None },
+            Err(e) => {
+                warn!("Failed to resync playbook {} after switching its reference: {}", id, e);
+                Resync { restarted: false, error: Some(e.to_string()) }
+            }
+        }
+    }
+
+    #[instrument(name = "PlaybookService::delete", skip_all, fields(%id))]
     pub async fn delete(ctx: Arc<Context>, id: Uuid) -> Result<u16> {
-        let playbooks = ctx.client.playbooks();
-        match playbooks.get(&id.to_string()).await {
+        let client = ctx.upstream.client();
+        let (playbooks, name) = (client.playbooks(), id.to_string());
+        match ctx.upstream.playbook(&id.to_string()).await? {
             Ok(_) => {
                 info!("delete playbooks in {}...", id);
-                playbooks.delete(&id.to_string()).await.map_err(ApiError::FailedToDeletePlaybook)
+                // Retried as deleting twice changes nothing, a retry finds it deleted if the first response was lost.
+                let status = match ctx.upstream.idempotent("playbooks.delete", || playbooks.delete(&name)).await? {
+                    Ok(status) => status,
+                    Err(e) if is_not_found(&e) => StatusCode::NO_CONTENT.as_u16(),
+                    Err(e) => return Err(ApiError::FailedToDeletePlaybook(e)),
+                };
+                Self::forget(&ctx, id).await;
+                Ok(status)
             }
             Err(e) => {
                 error!("Not found playbooks in {}, error: {}", id, e.to_string());
@@ -64,12 +493,33 @@ impl PlaybookService {
         }
     }
 
+    /// Forget everything kept locally about the playbook, once it's deleted from the Amphitheatre server.
+    pub(crate) async fn forget(ctx: &Context, id: Uuid) {
+        if let Err(e) = ctx.overlays.remove(id).await {
+            warn!("Failed to remove the workspace changes of playbook {}: {}", id, e);
+        }
+        ctx.activity.forget(id);
+        ctx.quotas.deleted(id);
+        ctx.events.forget(id);
+    }
+
+    #[instrument(name = "PlaybookService::start", skip_all, fields(%id))]
     pub async fn start(ctx: Arc<Context>, id: Uuid) -> Result<u16> {
-        let playbooks = ctx.client.playbooks();
-        match playbooks.get(&id.to_string()).await {
+        let client = ctx.upstream.client();
+        let playbooks = client.playbooks();
+        match ctx.upstream.playbook(&id.to_string()).await? {
             Ok(_) => {
                 info!("Start playbooks in {}...", id);
-                playbooks.start(&id.to_string()).await.map_err(ApiError::FailedToStartPlaybook)
+                ctx.quotas.check_running(id)?;
+                ctx.activity.touch(id);
+                let status =
+                    ctx.upstream.call("playbooks.start", playbooks.start(&id.to_string())).await?.map_err(|e| {
+                        ctx.events.transition(id, EventKind::Failed, Some(e.to_string()));
+                        ApiError::FailedToStartPlaybook(e)
+                    })?;
+                ctx.quotas.started(id);
+                ctx.events.transition(id, EventKind::Building, None);
+                Ok(status)
             }
             Err(e) => {
                 error!("Not found playbooks in {}, error: {}", id, e.to_string());
@@ -77,4 +527,172 @@ impl PlaybookService {
             }
         }
     }
+
+    #[instrument(name = "PlaybookService::stop", skip_all, fields(%id))]
+    pub async fn stop(ctx: Arc<Context>, id: Uuid) -> Result<u16> {
+        let client = ctx.upstream.client();
+        let (playbooks, name) = (client.playbooks(), id.to_string());
+        match ctx.upstream.playbook(&id.to_string()).await? {
+            Ok(_) => {
+                info!("Stop playbooks in {}...", id);
+                // Retried as stopping twice changes nothing.
+                let status = ctx
+                    .upstream
+                    .idempotent("playbooks.stop", || playbooks.stop(&name))
+                    .await?
+                    .map_err(ApiError::FailedToStopPlaybook)?;
+                ctx.quotas.stopped(id);
+                ctx.events.transition(id, EventKind::Stopped, None);
+                Ok(status)
+            }
+            Err(e) => {
+                error!("Not found playbooks in {}, error: {}", id, e.to_string());
+                Err(ApiError::NotFoundPlaybook(e))
+            }
+        }
+    }
+
+    /// Wait for the playbook to be running, i.e. its characters are resolved and deployed.
+    ///
+    /// The playbook is observed the same way as for its event stream, and only the failures of the playbook
+    /// observed after the wait began fail it, so a failure left from an earlier attempt doesn't. The upstream
+    /// failing to answer, e.g. a slow poll, is waited through until the deadline.
+    #[instrument(name = "PlaybookService::wait", skip_all, fields(%id))]
+    pub async fn wait(ctx: Arc<Context>, id: &str) -> Result<PlaybookSpec> {
+        let deadline = Instant::now() + Duration::from_secs(ctx.config.provision_timeout);
+        let began = ctx.events.last_seq();
+        loop {
+            match ctx.upstream.playbook(id).await {
+                Ok(Ok(playbook)) => {
+                    let uuid = Self::id(&playbook)?;
+                    EventService::observe(&ctx, uuid, &playbook);
+                    if ctx.events.state(uuid) == Some(EventKind::Running) {
+                        return Ok(playbook);
+                    }
+                    Self::check_failures(&ctx, uuid, began)?;
+                }
+                Ok(Err(e)) if !failing(&e) => return Err(ApiError::NotFoundPlaybook(e)),
+                Ok(Err(e)) => debug!("Failed to get playbook {} while waiting for it: {}", id, e),
+                Err(e) => debug!("Failed to get playbook {} while waiting for it: {}", id, e),
+            }
+            if Instant::now() >= deadline {
+                return Err(ApiError::FailedToProvision(format!("The playbook {} is not running in time", id)));
+            }
+
+            tokio::time::sleep(PROVISION_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Fail the wait if the playbook failed, or was stopped or deleted, since the given sequence number.
+    fn check_failures(ctx: &Context, id: Uuid, since: u64) -> Result<()> {
+        for event in ctx.events.since(id, since).into_iter().rev() {
+            match event.kind {
+                EventKind::Failed => {
+                    let message = event.message.unwrap_or_else(|| "unknown error".to_string());
+                    return Err(ApiError::FailedToProvision(message));
+                }
+                EventKind::Stopped | EventKind::Deleted => {
+                    return Err(ApiError::FailedToProvision(format!(
+                        "The playbook {} was {}",
+                        id,
+                        event.kind.as_str()
+                    )));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the playbook is running, its characters are only listed once they are resolved and deployed.
+    pub(crate) fn is_running(playbook: &PlaybookSpec) -> bool {
+        playbook.characters.as_ref().is_some_and(|c| !c.is_empty())
+    }
+
+    /// Parse the id of the playbook returned by the Amphitheatre server.
+    fn id(playbook: &PlaybookSpec) -> Result<Uuid> {
+        Uuid::parse_str(&playbook.id).map_err(|e| ApiError::BadPlaybook(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(paths: &[&str]) -> BTreeMap<String, String> {
+        paths.iter().map(|p| (p.to_string(), String::new())).collect()
+    }
+
+    #[test]
+    fn accepts_a_valid_manifest() {
+        let report = PlaybookService::check_manifest(b"name = \"hello\"\nversion = \"0.1.0\"\n".to_vec());
+        assert!(report.valid);
+        assert_eq!(report.character.as_deref(), Some("hello"));
+        assert!(report.errors.is_empty());
+    }
+
+    #[test]
+    fn reports_an_invalid_manifest() {
+        let report = PlaybookService::check_manifest(b"name = \"hello".to_vec());
+        assert!(!report.valid);
+        assert_eq!(report.character, None);
+        assert_eq!(report.errors[0].field, MANIFEST_FILE);
+
+        let report = PlaybookService::check_manifest(vec![0xff, 0xfe]);
+        assert!(!report.valid);
+        assert!(report.errors[0].message.contains("UTF-8"));
+
+        let report = PlaybookService::check_manifest(b"name = \" \"\nversion = \"0.1.0\"\n".to_vec());
+        assert!(!report.valid);
+        assert_eq!(report.errors[0].message, "The character name is empty");
+    }
+
+    #[test]
+    fn tells_a_missing_manifest_from_the_failures() {
+        use amp_common::http::HTTPError;
+        use amp_common::scm::errors::SCMError;
+
+        assert!(is_missing(&SCMError::ClientError(HTTPError::NotFound(MANIFEST_FILE.into()))));
+        assert!(is_missing(&SCMError::ClientError(HTTPError::Transport(404, "Not Found".into()))));
+        assert!(!is_missing(&SCMError::ClientError(HTTPError::Transport(0, "connection refused".into()))));
+        assert!(!is_missing(&SCMError::ClientError(HTTPError::Transport(502, "Bad Gateway".into()))));
+        assert!(!is_missing(&SCMError::ClientError(HTTPError::Unauthorized)));
+    }
+
+    #[test]
+    fn checks_the_language() {
+        let templates = TemplateRegistry::default();
+        assert_eq!(PlaybookService::check_language(&templates, None).unwrap(), DEFAULT_LANGUAGE);
+        assert_eq!(PlaybookService::check_language(&templates, Some(" Rust ")).unwrap(), "rust");
+        assert!(matches!(
+            PlaybookService::check_language(&templates, Some("cobol")),
+            Err(ApiError::BadPlaybookRequest(_))
+        ));
+        assert!(matches!(
+            PlaybookService::check_language(&templates, Some("rust\"\nname = \"x")),
+            Err(ApiError::BadPlaybookRequest(_))
+        ));
+    }
+
+    #[test]
+    fn builds_a_valid_manifest() {
+        let manifest = PlaybookService::manifest("playground-rust-1a2b3c4d", "Inline \"rust\"\u{7f} playbook").unwrap();
+        let character = toml::from_str::<Character>(&manifest).unwrap();
+        assert_eq!(character.meta.name, "playground-rust-1a2b3c4d");
+        assert_eq!(character.meta.description.as_deref(), Some("Inline \"rust\"\u{7f} playbook"));
+    }
+
+    #[test]
+    fn tells_the_language_of_an_inline_playbook() {
+        assert_eq!(PlaybookService::inline_language("playground-rust-1a2b3c4d"), Some("rust"));
+        assert_eq!(PlaybookService::inline_language("amphitheatre"), None);
+    }
+
+    #[test]
+    fn checks_the_inline_files() {
+        assert!(PlaybookService::check_files(&files(&["main.rs", "src/lib.rs"])).is_empty());
+        assert_eq!(PlaybookService::check_files(&files(&[])).len(), 1);
+        assert_eq!(PlaybookService::check_files(&files(&["", "/etc/passwd", "../secret", "a/../../b"])).len(), 4);
+    }
 }
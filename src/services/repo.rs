@@ -0,0 +1,78 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use tracing::{debug, instrument};
+
+use crate::context::Context;
+use crate::errors::{ApiError, Result};
+use crate::github::SearchItem;
+use crate::requests::repo::SearchRepositoryParams;
+use crate::responses::repo::{RepositoryItem, RepositorySearchResponse};
+
+/// The default number of repositories per page.
+const DEFAULT_PER_PAGE: u32 = 20;
+
+/// The maximum number of repositories per page allowed by GitHub.
+const MAX_PER_PAGE: u32 = 100;
+
+pub struct RepoService;
+
+impl RepoService {
+    /// Search repositories by keywords, the results are cached briefly to save the rate limit.
+    #[instrument(name = "RepoService::search", skip_all)]
+    pub async fn search(ctx: Arc<Context>, params: &SearchRepositoryParams) -> Result<RepositorySearchResponse> {
+        let q = params.q.trim();
+        if q.is_empty() {
+            return Err(ApiError::BadSearchRequest("The query is empty".to_string()));
+        }
+
+        let page = params.page.unwrap_or(1).max(1);
+        let per_page = params.per_page.unwrap_or(DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE);
+
+        let key = format!("{}:{}:{}", q, page, per_page);
+        if let Some(cached) = ctx.repo_search_cache.get(&key).await {
+            debug!("repository search cache hit: {}", key);
+            return Ok(cached);
+        }
+
+        let github = ctx.github.take()?;
+        let result = github
+            .search_repositories(q, page, per_page)
+            .await
+            .map_err(|e| github.search_error(e, ApiError::FailedToSearchRepos))?;
+
+        let response = RepositorySearchResponse {
+            total: result.total_count,
+            page,
+            per_page,
+            items: result.items.into_iter().map(RepositoryItem::from).collect(),
+        };
+        ctx.repo_search_cache.insert(key, response.clone()).await;
+
+        Ok(response)
+    }
+}
+
+impl From<SearchItem> for RepositoryItem {
+    fn from(item: SearchItem) -> Self {
+        RepositoryItem {
+            name: item.full_name,
+            description: item.description,
+            stars: item.stargazers_count,
+            default_branch: item.default_branch,
+            url: item.clone_url,
+        }
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use amp_common::scm::content::Content;
+use amp_common::scm::errors::SCMError;
+use futures::{stream, StreamExt, TryStreamExt};
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+use crate::context::Context;
+use crate::errors::{ApiError, Result};
+use crate::overlay;
+use crate::responses::snapshot::SnapshotResponse;
+use crate::snapshots::Snapshot;
+use crate::utils;
+use crate::utils::{is_missing, missing, unwrap_or_error};
+
+/// How many files are fetched from the upstream repository at the same time.
+const FETCH_CONCURRENCY: usize = 8;
+
+pub struct SnapshotService;
+
+impl SnapshotService {
+    /// Freeze the current files of the playbook, the upstream reference plus the workspace changes.
+    #[instrument(name = "SnapshotService::create", skip_all, fields(%id))]
+    pub async fn create(ctx: Arc<Context>, id: Uuid) -> Result<SnapshotResponse> {
+        let playbook = ctx.upstream.playbook(&id.to_string()).await?.map_err(ApiError::NotFoundPlaybook)?;
+
+        let mut files = BTreeMap::new();
+        let (mut repo, mut reference) = (None, None);
+
+        if let Some(source) = playbook.preface.repository {
+            let name = utils::repo(&source.repo)?;
+            let r = unwrap_or_error(source.reference(), "The reference is none")?;
+
+            let github = ctx.github.take()?;
+            let tree = github
+                .call("git.get_tree", github.client().git().get_tree(&name, &r, Some(true)))
+                .await
+                .map_err(|e| github.error(e, scm_error(ApiError::NotFoundFolder)))?
+                .ok_or_else(|| ApiError::NotFoundFolder(missing("The folder is none")))?;
+            let paths: Vec<String> = tree.tree.into_iter().filter(|e| e.kind == "blob").map(|e| e.path).collect();
+            if paths.len() > ctx.config.snapshot_max_files {
+                return Err(ApiError::FailedToSnapshot(format!(
+                    "The playbook has {} files, more than the limit of {}",
+                    paths.len(),
+                    ctx.config.snapshot_max_files
+                )));
+            }
+
+            let contents: Vec<(String, Vec<u8>)> = stream::iter(paths)
+                .map(|path| {
+                    let (github, name, r) = (&github, &name, &r);
+                    async move {
+                        let content = github
+                            .call("contents.find", github.client().contents().find(name, &path, r))
+                            .await
+                            .map_err(|e| github.error(e, scm_error(ApiError::NotFoundContent)))?;
+                        Ok::<_, ApiError>((path, content.data))
+                    }
+                })
+                .buffer_unordered(FETCH_CONCURRENCY)
+                .try_collect()
+                .await?;
+            files.extend(contents);
+
+            repo = Some(source.repo);
+            reference = Some(r);
+        }
+
+        // Apply the changes made on the workspace on top of the upstream files.
+        let changes = ctx.overlays.get(id).await.map_err(|e| ApiError::FailedToSnapshot(e.to_string()))?;
+        overlay::apply(&mut files, &changes);
+
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        let snapshot = Snapshot {
+            slug: String::new(),
+            playbook: playbook.id,
+            title: playbook.title,
+            repo,
+            reference,
+            created_at,
+            tree: vec![],
+        };
+        let snapshot =
+            ctx.snapshots.save(snapshot, files).await.map_err(|e| ApiError::FailedToSnapshot(e.to_string()))?;
+
+        info!("Created snapshot {} of playbook {} with {} files", snapshot.slug, id, snapshot.tree.len());
+        Ok(SnapshotResponse::from(&snapshot))
+    }
+
+    /// Returns the snapshot with its file tree.
+    #[instrument(name = "SnapshotService::get", skip_all, fields(%slug))]
+    pub async fn get(ctx: Arc<Context>, slug: String) -> Result<SnapshotResponse> {
+        Ok(SnapshotResponse::from(&Self::load(&ctx, &slug).await?))
+    }
+
+    /// Returns a file's content from the snapshot.
+    #[instrument(name = "SnapshotService::file", skip_all, fields(%slug))]
+    pub async fn file(ctx: Arc<Context>, slug: String, path: String) -> Result<Content> {
+        let snapshot = Self::load(&ctx, &slug).await?;
+        let data = ctx
+            .snapshots
+            .read(&snapshot, &path)
+            .await
+            .map_err(|e| ApiError::FailedToSnapshot(e.to_string()))?
+            .ok_or_else(|| ApiError::NotFoundContent(missing(&path)))?;
+
+        Ok(Content { path, data, sha: String::new(), blob_id: String::new() })
+    }
+
+    async fn load(ctx: &Context, slug: &str) -> Result<Snapshot> {
+        ctx.snapshots
+            .load(slug)
+            .await
+            .map_err(|e| ApiError::FailedToSnapshot(e.to_string()))?
+            .ok_or_else(|| ApiError::NotFoundSnapshot(slug.to_string()))
+    }
+}
+
+/// Tell the files the repository doesn't have from the failures to read them.
+fn scm_error(not_found: fn(SCMError) -> ApiError) -> impl FnOnce(SCMError) -> ApiError {
+    move |e| match is_missing(&e) {
+        true => not_found(e),
+        false => ApiError::FailedToReadRepo(e),
+    }
+}
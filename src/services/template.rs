@@ -0,0 +1,61 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tracing::instrument;
+
+use crate::context::Context;
+use crate::errors::{ApiError, Result};
+use crate::requests::playbook::CreatePlaybookRequest;
+use crate::templates::Template;
+
+pub struct TemplateService;
+
+impl TemplateService {
+    /// Returns all the available playbook templates.
+    #[instrument(name = "TemplateService::list", skip_all)]
+    pub fn list(ctx: &Context) -> Vec<Template> {
+        ctx.templates.list().to_vec()
+    }
+
+    /// Resolve a template to the create playbook request it stands for, the request can't
+    /// tell another source or language than the template's.
+    #[instrument(name = "TemplateService::resolve", skip_all, fields(%id))]
+    pub fn resolve(ctx: &Context, id: &str, req: &CreatePlaybookRequest) -> Result<CreatePlaybookRequest> {
+        let conflicts: Vec<&str> = [
+            ("repo", req.repo.is_some()),
+            ("branch", req.branch.is_some()),
+            ("tag", req.tag.is_some()),
+            ("rev", req.rev.is_some()),
+            ("files", req.files.is_some()),
+            ("language", req.language.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(field, given)| given.then_some(field))
+        .collect();
+        if !conflicts.is_empty() {
+            return Err(ApiError::BadPlaybookRequest(format!(
+                "The template {} can't be combined with {}",
+                id,
+                conflicts.join(", ")
+            )));
+        }
+
+        let template = ctx.templates.find(id).ok_or_else(|| ApiError::NotFoundTemplate(id.to_string()))?;
+        Ok(CreatePlaybookRequest {
+            title: req.title.clone(),
+            description: req.description.clone(),
+            ..CreatePlaybookRequest::from(template)
+        })
+    }
+}
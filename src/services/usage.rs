@@ -0,0 +1,30 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use tracing::instrument;
+
+use crate::context::Context;
+use crate::errors::Result;
+use crate::responses::usage::UsageResponse;
+
+pub struct UsageService;
+
+impl UsageService {
+    /// Returns the current usage of the user against the quotas.
+    #[instrument(name = "UsageService::get", skip_all, fields(%user))]
+    pub async fn get(ctx: Arc<Context>, user: &str) -> Result<UsageResponse> {
+        Ok(ctx.quotas.usage(&ctx.overlays, user))
+    }
+}
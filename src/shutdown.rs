@@ -0,0 +1,99 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::response::sse::Event;
+use futures::{stream, Stream, StreamExt};
+use tokio::sync::watch;
+
+/// The draining state of the server, shared by everything that must wind down before it exits.
+pub struct Shutdown {
+    sender: watch::Sender<bool>,
+    stopping: AtomicBool,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown { sender: watch::Sender::new(false), stopping: AtomicBool::new(false) }
+    }
+}
+
+impl Shutdown {
+    /// Announce the shutdown: the readiness probe fails from then on, so the load balancers stop
+    /// routing new traffic here, while the requests are still served as usual until the drain.
+    pub fn announce(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the server is shutting down, announced or draining already.
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst) || self.is_draining()
+    }
+
+    /// Start draining: no new connections, and the open streams are ended.
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Resolves once the server starts draining.
+    pub fn signalled(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut receiver = self.sender.subscribe();
+        async move {
+            let _ = receiver.wait_for(|draining| *draining).await;
+        }
+    }
+
+    /// End the SSE stream when the server starts draining, with a final `shutdown` event,
+    /// so the client knows to reconnect rather than the stream being cut off.
+    pub fn drain<S, E>(&self, events: S) -> impl Stream<Item = Result<Event, E>> + Send + 'static
+    where
+        S: Stream<Item = Result<Event, E>> + Send + 'static,
+        E: Send + 'static,
+    {
+        let receiver = self.sender.subscribe();
+        let farewell = stream::once(async move { *receiver.borrow() }).filter_map(|draining| async move {
+            draining.then(|| Ok(Event::default().event("shutdown").data("The server is shutting down")))
+        });
+
+        events.take_until(self.signalled()).chain(farewell)
+    }
+}
+
+/// Resolves on SIGINT (Ctrl+C) or SIGTERM.
+pub async fn signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c().await.expect("failed to install the SIGINT handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install the SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {},
+        _ = terminate => {},
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::bail;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// The length of the generated snapshot slugs.
+const SLUG_LENGTH: usize = 10;
+
+const SLUG_ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// How many slugs are tried before giving up, a collision is already unlikely with 62^10 slugs.
+const SLUG_ATTEMPTS: usize = 5;
+
+/// The file describing the snapshot, next to its files.
+const META_FILE: &str = "snapshot.json";
+
+/// A frozen, read-only copy of a playbook's files.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub slug: String,
+    /// The id of the playbook the snapshot was taken from.
+    pub playbook: String,
+    pub title: String,
+    /// The upstream repository and reference the files were read from, if any.
+    pub repo: Option<String>,
+    pub reference: Option<String>,
+    /// When the snapshot was taken, in seconds since the Unix epoch.
+    pub created_at: u64,
+    /// The sorted paths of the files, relative to the workspace root.
+    pub tree: Vec<String>,
+}
+
+/// Persists the snapshots in a local directory, one directory per snapshot.
+///
+/// The snapshot is described in a JSON file, and each of its files is kept as is, named after
+/// its index in the tree, so a single file can be read without reading the others.
+#[derive(Clone, Debug)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl SnapshotStore {
+    pub fn new(data_dir: &Path) -> SnapshotStore {
+        SnapshotStore { dir: data_dir.join("snapshots") }
+    }
+
+    /// Generate a short random slug for a new snapshot.
+    pub fn slug() -> String {
+        let mut rng = rand::rng();
+        (0..SLUG_LENGTH).map(|_| SLUG_ALPHABET[rng.random_range(0..SLUG_ALPHABET.len())] as char).collect()
+    }
+
+    /// Persist the snapshot with its files under a new slug, returns the snapshot with its slug and tree.
+    pub async fn save(&self, mut snapshot: Snapshot, files: BTreeMap<String, Vec<u8>>) -> anyhow::Result<Snapshot> {
+        snapshot.slug = self.reserve(std::iter::repeat_with(Self::slug).take(SLUG_ATTEMPTS)).await?;
+        snapshot.tree = files.keys().cloned().collect();
+
+        let dir = self.dir.join(&snapshot.slug);
+        for (index, data) in files.values().enumerate() {
+            tokio::fs::write(dir.join(index.to_string()), data).await?;
+        }
+        // The snapshot only exists once it's described, so it's never read without all its files.
+        tokio::fs::write(dir.join(META_FILE), serde_json::to_vec(&snapshot)?).await?;
+
+        Ok(snapshot)
+    }
+
+    /// Load a snapshot by slug, returns `None` if there is no such snapshot.
+    pub async fn load(&self, slug: &str) -> anyhow::Result<Option<Snapshot>> {
+        // The slug ends up in a file name, so only accept the characters we generate.
+        if slug.is_empty() || !slug.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return Ok(None);
+        }
+
+        match tokio::fs::read(self.dir.join(slug).join(META_FILE)).await {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Read a file of the snapshot, returns `None` if the snapshot has no such file.
+    pub async fn read(&self, snapshot: &Snapshot, path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let Ok(index) = snapshot.tree.binary_search_by(|p| p.as_str().cmp(path)) else { return Ok(None) };
+
+        Ok(Some(tokio::fs::read(self.dir.join(&snapshot.slug).join(index.to_string())).await?))
+    }
+
+    /// Create the directory of the first slug that's not taken yet, and returns the slug.
+    async fn reserve(&self, slugs: impl Iterator<Item = String>) -> anyhow::Result<String> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        for slug in slugs {
+            match tokio::fs::create_dir(self.dir.join(&slug)).await {
+                Ok(()) => return Ok(slug),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        bail!("No free slug for the snapshot after {} attempts", SLUG_ATTEMPTS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> SnapshotStore {
+        SnapshotStore::new(&std::env::temp_dir().join(format!("snapshots-{}", SnapshotStore::slug())))
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            slug: String::new(),
+            playbook: "8f4b0a9e-5d0e-4b53-9d57-0e0c3f6b1a2c".to_string(),
+            title: "hello".to_string(),
+            repo: None,
+            reference: None,
+            created_at: 0,
+            tree: vec![],
+        }
+    }
+
+    #[test]
+    fn generates_the_slugs_from_the_whole_alphabet() {
+        let slugs: Vec<String> = (0..200).map(|_| SnapshotStore::slug()).collect();
+        assert!(slugs.iter().all(|s| s.len() == SLUG_LENGTH && s.bytes().all(|b| SLUG_ALPHABET.contains(&b))));
+
+        // Every position takes all sorts of characters, none is fixed.
+        for i in 0..SLUG_LENGTH {
+            let distinct: std::collections::HashSet<u8> = slugs.iter().map(|s| s.as_bytes()[i]).collect();
+            assert!(distinct.len() > 16, "position {} only takes {} characters", i, distinct.len());
+        }
+    }
+
+    #[tokio::test]
+    async fn skips_the_slugs_taken() {
+        let store = store();
+        assert_eq!(store.reserve(["taken".to_string()].into_iter()).await.unwrap(), "taken");
+
+        let slugs = ["taken".to_string(), "free".to_string()];
+        assert_eq!(store.reserve(slugs.into_iter()).await.unwrap(), "free");
+        assert!(store.reserve(["taken".to_string()].into_iter()).await.is_err());
+        std::fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reads_a_single_file() {
+        let store = store();
+        let files = BTreeMap::from([
+            ("src/main.rs".to_string(), b"fn main() {}".to_vec()),
+            ("logo.png".to_string(), vec![0x89, 0x50, 0x4e, 0x47, 0xff]),
+        ]);
+        let saved = store.save(snapshot(), files).await.unwrap();
+        assert_eq!(saved.tree, ["logo.png", "src/main.rs"]);
+
+        let loaded = store.load(&saved.slug).await.unwrap().unwrap();
+        assert_eq!(loaded.tree, saved.tree);
+        assert_eq!(store.read(&loaded, "logo.png").await.unwrap().unwrap(), [0x89, 0x50, 0x4e, 0x47, 0xff]);
+        assert_eq!(store.read(&loaded, "src/main.rs").await.unwrap().unwrap(), b"fn main() {}");
+        assert!(store.read(&loaded, "src/lib.rs").await.unwrap().is_none());
+
+        assert!(store.load("missing").await.unwrap().is_none());
+        assert!(store.load("../snapshots").await.unwrap().is_none());
+        std::fs::remove_dir_all(&store.dir).unwrap();
+    }
+}
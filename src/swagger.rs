@@ -12,17 +12,30 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use utoipa::OpenApi;
+use utoipa::openapi::path::Operation;
+use utoipa::openapi::{Content, Ref, RefOr, Response};
+use utoipa::{Modify, OpenApi};
 use utoipa_swagger_ui::SwaggerUi;
 
-use crate::{handlers, requests};
+use crate::errors::PROBLEM_CONTENT_TYPE;
+use crate::{errors, events, handlers, operations, requests, responses, templates};
 
 #[derive(OpenApi)]
 #[openapi(
     paths(
+        handlers::health::healthz,
+        handlers::health::readyz,
+        handlers::health::version,
+        handlers::metrics::metrics,
+
         handlers::playbook::create,
+        handlers::playbook::validate,
+        handlers::playbook::update,
         handlers::playbook::delete,
         handlers::playbook::start,
+        handlers::playbook::fork,
+
+        handlers::event::events,
 
         handlers::logger::logs,
 
@@ -39,13 +52,54 @@ use crate::{handlers, requests};
         handlers::folder::delete,
         handlers::folder::copy,
         handlers::folder::rename,
+
+        handlers::snapshot::create,
+        handlers::snapshot::get,
+        handlers::snapshot::file,
+
+        handlers::operation::get,
+
+        handlers::repo::search,
+
+        handlers::template::list,
+
+        handlers::usage::get,
+
+        handlers::diagnostics::github,
     ),
     components(
         schemas(
+            errors::Problem,
+            errors::ErrorCode,
+
+            events::PlaybookEvent,
+            events::EventKind,
+
+            operations::Operation,
+            operations::OperationStatus,
+
             requests::playbook::CreatePlaybookRequest,
+            requests::playbook::UpdatePlaybookRequest,
             requests::file::FileRequest,
             requests::file::DestinationRequest,
 
+            responses::diagnostics::GitHubDiagnosticsResponse,
+            responses::diagnostics::TokenStatus,
+            responses::health::ReadinessResponse,
+            responses::health::DependencyStatus,
+            responses::health::VersionResponse,
+            responses::playbook::UpdatePlaybookResponse,
+            responses::playbook::Resync,
+            responses::playbook::ValidationReport,
+            responses::playbook::ValidationError,
+            responses::repo::RepositorySearchResponse,
+            responses::repo::RepositoryItem,
+            responses::snapshot::SnapshotResponse,
+            responses::usage::UsageResponse,
+            responses::usage::Usage,
+
+            templates::Template,
+
             amp_common::resource::ActorSpec,
             amp_common::resource::CharacterSpec,
             amp_common::resource::Partner,
@@ -70,12 +124,52 @@ use crate::{handlers, requests};
         )
     ),
     tags(
+        (name = "Health", description = "The Health Service Handlers"),
         (name = "Playbooks", description = "The Playbooks Service Handlers"),
+        (name = "Events", description = "The Events Service Handlers"),
         (name = "Logging", description = "The Logging Service Handlers"),
+        (name = "Operations", description = "The Operations Service Handlers"),
+        (name = "Snapshots", description = "The Snapshots Service Handlers"),
+        (name = "Repositories", description = "The Repositories Service Handlers"),
+        (name = "Templates", description = "The Templates Service Handlers"),
+        (name = "Usage", description = "The Usage Service Handlers"),
+        (name = "Diagnostics", description = "The Diagnostics Service Handlers"),
     ),
+    modifiers(&ProblemResponses),
 )]
 struct ApiDoc;
 
+/// Document the error responses of every operation as `application/problem+json`.
+struct ProblemResponses;
+
+impl Modify for ProblemResponses {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        for item in openapi.paths.paths.values_mut() {
+            let operations = [&mut item.get, &mut item.put, &mut item.post, &mut item.delete, &mut item.patch];
+            for operation in operations.into_iter().flatten() {
+                document(operation);
+            }
+        }
+    }
+}
+
+fn document(operation: &mut Operation) {
+    for (status, response) in operation.responses.responses.iter_mut() {
+        if let RefOr::T(response) = response {
+            if (status.starts_with('4') || status.starts_with('5')) && response.content.is_empty() {
+                response.content.insert(PROBLEM_CONTENT_TYPE.to_string(), problem());
+            }
+        }
+    }
+
+    let default = Response::builder().description("An error occurred").content(PROBLEM_CONTENT_TYPE, problem());
+    operation.responses.responses.entry("default".to_string()).or_insert_with(|| RefOr::T(default.build()));
+}
+
+fn problem() -> Content {
+    Content::new(Some(Ref::from_schema_name("Problem")))
+}
+
 pub fn build() -> SwaggerUi {
     SwaggerUi::new("/swagger").url("/openapi.json", ApiDoc::openapi())
 }
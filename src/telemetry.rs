@@ -0,0 +1,191 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::time::{Instant, UNIX_EPOCH};
+
+use axum::extract::{MatchedPath, Request};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::Response;
+use futures::future::BoxFuture;
+use opentelemetry::trace::{TraceError, TracerProvider as _};
+use opentelemetry::{global, KeyValue};
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use serde_json::json;
+use tracing::metadata::LevelFilter;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::{Config, LogFormat, TraceExporter};
+use crate::middleware::request_id::RequestId;
+use crate::user::{User, ANONYMOUS};
+
+/// Install the tracing subscriber, with the configured span exporter if any.
+/// The returned provider must be shut down before exiting, to flush the pending spans.
+pub fn init(config: &Config) -> anyhow::Result<Option<TracerProvider>> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let filter = EnvFilter::builder().with_default_directive(LevelFilter::DEBUG.into()).from_env_lossy();
+    let resource = Resource::new(vec![
+        KeyValue::new("service.name", env!("CARGO_PKG_NAME")),
+        KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+    ]);
+
+    let provider = match config.trace_exporter {
+        TraceExporter::None => None,
+        TraceExporter::Otlp => {
+            let exporter =
+                opentelemetry_otlp::SpanExporter::builder().with_http().with_endpoint(&config.otlp_endpoint).build()?;
+            Some(
+                TracerProvider::builder().with_batch_exporter(exporter, runtime::Tokio).with_resource(resource).build(),
+            )
+        }
+        TraceExporter::Stderr => {
+            let exporter = JsonExporter::new(Box::new(io::stderr()));
+            Some(TracerProvider::builder().with_simple_exporter(exporter).with_resource(resource).build())
+        }
+        TraceExporter::File => {
+            let file = OpenOptions::new().create(true).append(true).open(&config.trace_file)?;
+            let exporter = JsonExporter::new(Box::new(file));
+            Some(TracerProvider::builder().with_simple_exporter(exporter).with_resource(resource).build())
+        }
+    };
+
+    let otel = provider.as_ref().map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer(env!("CARGO_PKG_NAME"))));
+    let (pretty, json) = match config.log_format {
+        LogFormat::Pretty => (Some(tracing_subscriber::fmt::layer()), None),
+        LogFormat::Json => (None, Some(tracing_subscriber::fmt::layer().json().with_current_span(true))),
+    };
+    tracing_subscriber::registry().with(filter).with(pretty).with(json).with(otel).init();
+
+    if let Some(provider) = &provider {
+        global::set_tracer_provider(provider.clone());
+    }
+
+    Ok(provider)
+}
+
+/// Flush the pending spans and stop the exporter.
+pub fn shutdown(provider: Option<TracerProvider>) {
+    if let Some(Err(e)) = provider.map(|p| p.shutdown()) {
+        tracing::warn!("Failed to shut down the tracer provider: {}", e);
+    }
+}
+
+/// Open a span for each request, continuing the trace of the client if it sent a `traceparent` header,
+/// and write one access log line once it's handled.
+pub async fn trace(req: Request, next: Next) -> Response {
+    let method = req.method().clone();
+    let route = match req.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => req.uri().path().to_string(),
+    };
+    let request_id = req.extensions().get::<RequestId>().map(|id| id.0.clone()).unwrap_or_default();
+    // Resolved by the `identify` middleware, so the header of an untrusted client isn't logged as its user.
+    let user = req.extensions().get::<User>().map_or(ANONYMOUS, |User(user)| user.as_str()).to_string();
+    let path = req.uri().path().to_string();
+
+    let span = tracing::info_span!(
+        "request",
+        otel.name = %format!("{} {}", method, route),
+        otel.kind = "server",
+        http.request.method = %method,
+        http.route = %route,
+        http.response.status_code = tracing::field::Empty,
+        request_id = %request_id,
+    );
+    let parent = global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(req.headers())));
+    span.set_parent(parent);
+
+    let started = Instant::now();
+    let response = next.run(req).instrument(span.clone()).await;
+    span.record("http.response.status_code", response.status().as_u16());
+
+    tracing::info!(
+        target: "access",
+        parent: &span,
+        method = %method,
+        path = %path,
+        route = %route,
+        status = response.status().as_u16(),
+        latency_ms = started.elapsed().as_millis() as u64,
+        request_id = %request_id,
+        user = %user,
+        "{} {} {}", method, path, response.status().as_u16()
+    );
+
+    response
+}
+
+/// The W3C trace context headers of the current span, for the requests sent to GitHub with
+/// `ctx.http_client` and to the Amphitheatre server with `ctx.upstream.client()`.
+pub fn headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let context = tracing::Span::current().context();
+    global::get_text_map_propagator(|p| p.inject_context(&context, &mut HeaderInjector(&mut headers)));
+
+    headers
+}
+
+/// Write the finished spans as JSON lines, to follow the traces without a collector.
+struct JsonExporter {
+    writer: Box<dyn Write + Send + Sync>,
+}
+
+impl JsonExporter {
+    fn new(writer: Box<dyn Write + Send + Sync>) -> JsonExporter {
+        JsonExporter { writer }
+    }
+}
+
+impl fmt::Debug for JsonExporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("JsonExporter")
+    }
+}
+
+impl SpanExporter for JsonExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        let result = batch.iter().try_for_each(|span| {
+            let nanos = |time: std::time::SystemTime| time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+            let attributes: serde_json::Map<_, _> =
+                span.attributes.iter().map(|kv| (kv.key.to_string(), json!(kv.value.to_string()))).collect();
+            let line = json!({
+                "trace_id": span.span_context.trace_id().to_string(),
+                "span_id": span.span_context.span_id().to_string(),
+                "parent_span_id": span.parent_span_id.to_string(),
+                "name": span.name,
+                "kind": format!("{:?}", span.span_kind),
+                "start_time_unix_nano": nanos(span.start_time),
+                "end_time_unix_nano": nanos(span.end_time),
+                "attributes": attributes,
+                "status": format!("{:?}", span.status),
+            });
+            writeln!(self.writer, "{}", line)
+        });
+
+        Box::pin(std::future::ready(result.map_err(|e| TraceError::Other(Box::new(e)))))
+    }
+}
@@ -0,0 +1,219 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context as _};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::requests::playbook::CreatePlaybookRequest;
+
+/// A preset to start a playbook from, backed by either a repository or a set of inline files.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Template {
+    /// The unique id of the template, e.g. rust-axum.
+    #[serde(default)]
+    pub id: String,
+    /// The display title of the template, e.g. Rust + axum.
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The language of the template, e.g. rust, go, node or python.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Source code repository the playbook should be cloned from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// Inline source files keyed by the path relative to the workspace root.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<BTreeMap<String, String>>,
+}
+
+impl From<&Template> for CreatePlaybookRequest {
+    fn from(template: &Template) -> Self {
+        CreatePlaybookRequest {
+            repo: template.repo.clone(),
+            branch: template.branch.clone(),
+            tag: template.tag.clone(),
+            rev: template.rev.clone(),
+            files: template.files.clone(),
+            language: template.language.clone(),
+            template_id: None,
+            title: None,
+            description: None,
+        }
+    }
+}
+
+/// The catalogue file format, a list of `[[templates]]` tables.
+#[derive(Deserialize)]
+struct Catalogue {
+    #[serde(default)]
+    templates: Vec<Template>,
+}
+
+/// The registry of the available playbook templates, loaded once at startup.
+#[derive(Clone, Debug, Default)]
+pub struct TemplateRegistry {
+    templates: Vec<Template>,
+}
+
+impl TemplateRegistry {
+    /// Load the templates from a TOML catalogue file, or from a directory
+    /// containing one TOML file per template (the file stem is the default id).
+    pub fn load(path: &Path) -> anyhow::Result<TemplateRegistry> {
+        let mut templates = vec![];
+
+        if path.is_dir() {
+            let mut entries = fs::read_dir(path)
+                .with_context(|| format!("failed to read templates directory {}", path.display()))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "toml"))
+                .collect::<Vec<_>>();
+            entries.sort();
+
+            for entry in entries {
+                let mut template: Template = toml::from_str(&fs::read_to_string(&entry)?)
+                    .with_context(|| format!("failed to parse template {}", entry.display()))?;
+                if template.id.is_empty() {
+                    template.id = entry.file_stem().unwrap_or_default().to_string_lossy().to_string();
+                }
+                templates.push(template);
+            }
+        } else {
+            let catalogue: Catalogue = toml::from_str(
+                &fs::read_to_string(path)
+                    .with_context(|| format!("failed to read templates file {}", path.display()))?,
+            )
+            .with_context(|| format!("failed to parse templates file {}", path.display()))?;
+            templates = catalogue.templates;
+        }
+
+        for template in &templates {
+            if template.id.is_empty() {
+                bail!("the template {:?} has no id", template.title);
+            }
+            if template.repo.is_some() == template.files.is_some() {
+                bail!("the template {} requires either repo or files", template.id);
+            }
+            if templates.iter().filter(|t| t.id == template.id).count() > 1 {
+                bail!("the template {} is defined more than once", template.id);
+            }
+        }
+
+        Ok(TemplateRegistry { templates })
+    }
+
+    /// Returns all the templates.
+    pub fn list(&self) -> &[Template] {
+        &self.templates
+    }
+
+    /// Find a template by id.
+    pub fn find(&self, id: &str) -> Option<&Template> {
+        self.templates.iter().find(|t| t.id == id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A fresh directory with the given files.
+    fn dir(files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("templates-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, content) in files {
+            fs::write(dir.join(name), content).unwrap();
+        }
+        dir
+    }
+
+    const RUST: &str = r#"
+        title = "Rust"
+        language = "rust"
+        files = { "src/main.rs" = "fn main() {}" }
+    "#;
+
+    #[test]
+    fn loads_a_catalogue_file() {
+        let catalogue = r#"
+            [[templates]]
+            id = "rust-axum"
+            title = "Rust + axum"
+            repo = "https://github.com/tokio-rs/axum.git"
+            branch = "main"
+
+            [[templates]]
+            id = "rust"
+            title = "Rust"
+            files = { "src/main.rs" = "fn main() {}" }
+        "#;
+        let registry = TemplateRegistry::load(&dir(&[("templates.toml", catalogue)]).join("templates.toml")).unwrap();
+
+        let ids: Vec<&str> = registry.list().iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, ["rust-axum", "rust"]);
+        assert_eq!(registry.find("rust-axum").unwrap().branch.as_deref(), Some("main"));
+        assert!(registry.find("go").is_none());
+    }
+
+    #[test]
+    fn loads_a_directory_with_the_file_stems_as_ids() {
+        let node = r#"
+            id = "node-express"
+            title = "Node + Express"
+            repo = "https://github.com/expressjs/express.git"
+        "#;
+        let dir = dir(&[("rust.toml", RUST), ("node.toml", node), ("README.md", "not a template")]);
+        let registry = TemplateRegistry::load(&dir).unwrap();
+
+        let ids: Vec<&str> = registry.list().iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, ["node-express", "rust"]);
+    }
+
+    #[test]
+    fn rejects_the_duplicate_ids() {
+        let dir = dir(&[("rust.toml", RUST), ("other.toml", &format!("id = \"rust\"\n{}", RUST))]);
+        let error = TemplateRegistry::load(&dir).unwrap_err();
+        assert!(error.to_string().contains("more than once"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_the_missing_ids() {
+        let catalogue = r#"
+            [[templates]]
+            title = "Rust"
+            files = { "src/main.rs" = "fn main() {}" }
+        "#;
+        let error = TemplateRegistry::load(&dir(&[("templates.toml", catalogue)]).join("templates.toml")).unwrap_err();
+        assert!(error.to_string().contains("has no id"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_the_templates_without_a_single_source() {
+        let error = TemplateRegistry::load(&dir(&[("empty.toml", "title = \"Empty\"")])).unwrap_err();
+        assert!(error.to_string().contains("requires either repo or files"), "{}", error);
+    }
+}
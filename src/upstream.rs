@@ -0,0 +1,311 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use amp_client::client::Client;
+use amp_common::http::HTTPError;
+use amp_common::resource::PlaybookSpec;
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+use crate::errors::ApiError;
+use crate::monitor;
+use crate::telemetry;
+
+/// The name of the upstream in the metrics and errors.
+const SERVICE: &str = "amphitheatre";
+
+/// The longest wait between two retries, however many there are.
+pub(crate) const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// The outcome of a call let through the circuit breaker: the upstream's own answer,
+/// or `ApiError::UpstreamUnavailable` if the call wasn't made at all.
+pub type Outcome<T> = Result<Result<T, HTTPError>, ApiError>;
+
+/// Makes the calls to the Amphitheatre server resilient to a flapping server: every call has
+/// a timeout, the idempotent ones are retried with an exponential backoff, and a circuit breaker
+/// fails the calls fast while the server keeps failing, rather than piling them up.
+pub struct Upstream {
+    client: Arc<Client>,
+    server: String,
+    token: Option<String>,
+    timeout: Duration,
+    retries: u32,
+    backoff: Duration,
+    breaker: CircuitBreaker,
+}
+
+impl Upstream {
+    pub fn new(config: &Config, client: Arc<Client>) -> Upstream {
+        Upstream {
+            client,
+            server: config.amp_server.clone(),
+            token: config.auth_token.clone(),
+            timeout: Duration::from_secs(config.upstream_timeout),
+            retries: config.upstream_retries,
+            backoff: Duration::from_millis(config.upstream_backoff),
+            breaker: CircuitBreaker::new(config.breaker_threshold, Duration::from_secs(config.breaker_cooldown)),
+        }
+    }
+
+    /// The client to make the calls of the current span with, sending its trace context to the
+    /// Amphitheatre server. `amp_client::Client` sets the headers of its requests itself, so they're
+    /// carried as the default headers of a client of their own, only built while a trace is recorded.
+    pub fn client(&self) -> Arc<Client> {
+        let headers = telemetry::headers();
+        if headers.is_empty() {
+            return self.client.clone();
+        }
+
+        match reqwest::Client::builder().user_agent(env!("CARGO_PKG_NAME")).default_headers(headers).build() {
+            Ok(http) => Arc::new(Client::with_http_client(&self.server, self.token.clone(), http)),
+            Err(e) => {
+                warn!("Failed to build the traced {} client, calling it untraced: {}", SERVICE, e);
+                self.client.clone()
+            }
+        }
+    }
+
+    /// Get the playbook, retried as it's the most frequent call of all.
+    pub async fn playbook(&self, id: &str) -> Outcome<PlaybookSpec> {
+        let client = self.client();
+        self.idempotent("playbooks.get", || async { client.playbooks().get(id).await }).await
+    }
+
+    /// Make a call once, e.g. creating a playbook, which must not be repeated if the response is lost.
+    pub async fn call<T>(
+        &self,
+        operation: &'static str,
+        call: impl Future<Output = Result<T, HTTPError>>,
+    ) -> Outcome<T> {
+        self.breaker.acquire()?;
+        let result = self.attempt(operation, call).await;
+        self.breaker.record(result.as_ref().err().is_some_and(failing));
+
+        Ok(result)
+    }
+
+    /// Make an idempotent call, retrying it while the upstream is failing, up to the configured retries.
+    pub async fn idempotent<T, F, Fut>(&self, operation: &'static str, call: F) -> Outcome<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, HTTPError>>,
+    {
+        let mut backoff = self.backoff;
+        let mut retries = 0;
+        loop {
+            self.breaker.acquire()?;
+            let result = self.attempt(operation, call()).await;
+            let failed = result.as_ref().err().is_some_and(failing);
+            self.breaker.record(failed);
+
+            if !failed || retries >= self.retries {
+                return Ok(result);
+            }
+
+            retries += 1;
+            metrics::counter!("playground_upstream_retries_total", "service" => SERVICE, "operation" => operation)
+                .increment(1);
+            debug!("Retrying {} in {:?} ({}/{})", operation, backoff, retries, self.retries);
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    async fn attempt<T>(
+        &self,
+        operation: &'static str,
+        call: impl Future<Output = Result<T, HTTPError>>,
+    ) -> Result<T, HTTPError> {
+        monitor::amp(operation, async {
+            match tokio::time::timeout(self.timeout, call).await {
+                Ok(result) => result,
+                Err(_) => Err(HTTPError::GatewayTimeout(format!("No response in {}s", self.timeout.as_secs()))),
+            }
+        })
+        .await
+    }
+}
+
+/// Whether the Amphitheatre server says the playbook doesn't exist, e.g. deleted by another client.
+pub(crate) fn is_not_found(e: &HTTPError) -> bool {
+    matches!(e, HTTPError::NotFound(_) | HTTPError::Transport(404, _))
+}
+
+/// Whether the upstream is failing, rather than answering with an error of its own like 404.
+pub(crate) fn failing(e: &HTTPError) -> bool {
+    match e {
+        HTTPError::GatewayTimeout(_) => true,
+        HTTPError::Transport(code, _) => *code == 0 || *code >= 500,
+        _ => false,
+    }
+}
+
+/// Opens after the consecutive failures reach the threshold, rejecting the calls until the cooldown
+/// is over, then lets a single trial call through to tell whether the upstream has recovered.
+struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// The trial call is in flight, if it's never recorded, e.g. as it was cancelled,
+    /// another one is let through after the cooldown.
+    HalfOpen {
+        since: Instant,
+    },
+}
+
+impl CircuitBreaker {
+    fn new(threshold: u32, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker { threshold, cooldown, state: Mutex::new(State::Closed { failures: 0 }) }
+    }
+
+    /// Let the call through, or tell the caller when to retry.
+    fn acquire(&self) -> Result<(), ApiError> {
+        // A threshold of zero disables the breaker.
+        if self.threshold == 0 {
+            return Ok(());
+        }
+
+        let mut state = self.state.lock().map_err(|_| ApiError::InternalServerError)?;
+        let now = Instant::now();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if now < until => Err(unavailable(until - now)),
+            State::HalfOpen { since } if now < since + self.cooldown => Err(unavailable(since + self.cooldown - now)),
+            State::Open { .. } | State::HalfOpen { .. } => {
+                *state = State::HalfOpen { since: now };
+                Ok(())
+            }
+        }
+    }
+
+    fn record(&self, failed: bool) {
+        if self.threshold == 0 {
+            return;
+        }
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+
+        let next = match (*state, failed) {
+            (State::Closed { .. }, false) => State::Closed { failures: 0 },
+            (State::Closed { failures }, true) if failures + 1 < self.threshold => {
+                State::Closed { failures: failures + 1 }
+            }
+            (State::HalfOpen { .. }, false) => {
+                info!("The {} server has recovered, closing the circuit breaker", SERVICE);
+                State::Closed { failures: 0 }
+            }
+            (State::Closed { .. } | State::HalfOpen { .. }, true) => {
+                warn!("The {} server keeps failing, opening the circuit breaker for {:?}", SERVICE, self.cooldown);
+                State::Open { until: Instant::now() + self.cooldown }
+            }
+            // The calls let through before the breaker opened don't change it.
+            (State::Open { .. }, _) => *state,
+        };
+        *state = next;
+
+        let open = matches!(next, State::Open { .. } | State::HalfOpen { .. });
+        metrics::gauge!("playground_upstream_circuit_open", "service" => SERVICE).set(if open { 1.0 } else { 0.0 });
+    }
+}
+
+fn unavailable(retry_after: Duration) -> ApiError {
+    ApiError::UpstreamUnavailable { service: SERVICE, retry_after: retry_after.as_secs().max(1) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_millis(50);
+
+    fn is_open(breaker: &CircuitBreaker) -> bool {
+        matches!(breaker.acquire(), Err(ApiError::UpstreamUnavailable { .. }))
+    }
+
+    #[test]
+    fn opens_after_the_consecutive_failures() {
+        let breaker = CircuitBreaker::new(3, COOLDOWN);
+        breaker.record(true);
+        breaker.record(true);
+        // A success in between starts the count over.
+        breaker.record(false);
+        breaker.record(true);
+        breaker.record(true);
+        assert!(breaker.acquire().is_ok());
+
+        breaker.record(true);
+        assert!(is_open(&breaker));
+    }
+
+    #[test]
+    fn lets_a_single_trial_through_after_the_cooldown() {
+        let breaker = CircuitBreaker::new(1, COOLDOWN);
+        breaker.record(true);
+        assert!(is_open(&breaker));
+
+        std::thread::sleep(COOLDOWN);
+        assert!(breaker.acquire().is_ok());
+        assert!(is_open(&breaker), "only one trial call is let through");
+
+        // The trial failing opens it again, succeeding closes it.
+        breaker.record(true);
+        assert!(is_open(&breaker));
+        std::thread::sleep(COOLDOWN);
+        assert!(breaker.acquire().is_ok());
+        breaker.record(false);
+        assert!(breaker.acquire().is_ok());
+        assert!(breaker.acquire().is_ok());
+    }
+
+    #[test]
+    fn ignores_the_calls_let_through_before_it_opened() {
+        let breaker = CircuitBreaker::new(1, COOLDOWN);
+        breaker.record(true);
+        breaker.record(false);
+        assert!(is_open(&breaker));
+    }
+
+    #[test]
+    fn is_disabled_by_a_zero_threshold() {
+        let breaker = CircuitBreaker::new(0, COOLDOWN);
+        for _ in 0..10 {
+            breaker.record(true);
+        }
+        assert!(breaker.acquire().is_ok());
+    }
+
+    #[test]
+    fn tells_the_failing_upstream_from_its_answers() {
+        assert!(failing(&HTTPError::GatewayTimeout("slow".to_string())));
+        assert!(failing(&HTTPError::Transport(0, "refused".to_string())));
+        assert!(failing(&HTTPError::Transport(503, "unavailable".to_string())));
+        assert!(!failing(&HTTPError::Transport(404, "missing".to_string())));
+        assert!(!failing(&HTTPError::NotFound("missing".to_string())));
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::convert::Infallible;
+use std::net::IpAddr;
+
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::HeaderMap;
+use ipnet::IpNet;
+
+/// The header carrying the id of the current user, set by the Playground frontend or gateway.
+pub const USER_HEADER: &str = "x-user-id";
+
+/// The header listing the client IP and the proxies the request went through, the nearest last.
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// The user assumed when the request tells neither the user nor the client IP.
+pub const ANONYMOUS: &str = "anonymous";
+
+/// The user on whose behalf the request is made: the id told by a trusted proxy, otherwise
+/// the client IP, e.g. `ip:192.0.2.1`, as any client could send the header.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct User(pub String);
+
+impl User {
+    /// Resolve the user of the request coming from the peer, `None` for the Unix domain socket,
+    /// which only the local proxies can reach, so they are trusted like the configured ones.
+    pub fn resolve(headers: &HeaderMap, peer: Option<IpAddr>, trusted_proxies: &[IpNet]) -> User {
+        let trusted = peer.is_none_or(|ip| trusted_proxies.iter().any(|net| net.contains(&ip)));
+        let user = trusted
+            .then(|| headers.get(USER_HEADER))
+            .flatten()
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty());
+
+        match (user, Self::client_ip(headers, peer, trusted_proxies)) {
+            (Some(user), _) => User(user.to_string()),
+            (None, Some(ip)) => User(format!("ip:{}", ip)),
+            (None, None) => User(ANONYMOUS.to_string()),
+        }
+    }
+
+    /// The IP of the client: the peer itself, unless it's a trusted proxy, then the right-most hop
+    /// of `X-Forwarded-For` not added by a trusted proxy, as the ones on its left could be made up.
+    fn client_ip(headers: &HeaderMap, peer: Option<IpAddr>, trusted_proxies: &[IpNet]) -> Option<IpAddr> {
+        let trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+        if peer.is_some_and(|ip| !trusted(&ip)) {
+            return peer;
+        }
+
+        let hops: Vec<&str> = headers
+            .get_all(FORWARDED_FOR_HEADER)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .collect();
+        for hop in hops.into_iter().rev() {
+            match hop.parse::<IpAddr>() {
+                Ok(ip) if trusted(&ip) => continue,
+                Ok(ip) => return Some(ip),
+                // The hops past a malformed one can't be told apart from made up ones.
+                Err(_) => break,
+            }
+        }
+
+        peer
+    }
+}
+
+/// The user resolved by the `identify` middleware, or the anonymous one without it.
+impl<S: Send + Sync> FromRequestParts<S> for User {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<User>().cloned().unwrap_or_else(|| User(ANONYMOUS.to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(user: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_HEADER, user.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn trusts_the_header_from_the_trusted_proxies_only() {
+        let proxies: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+        let gateway = Some("10.1.2.3".parse().unwrap());
+        let client = Some("192.0.2.1".parse().unwrap());
+
+        assert_eq!(User::resolve(&headers("alice"), gateway, &proxies), User("alice".to_string()));
+        assert_eq!(User::resolve(&headers("alice"), None, &proxies), User("alice".to_string()));
+        assert_eq!(User::resolve(&headers("alice"), client, &proxies), User("ip:192.0.2.1".to_string()));
+        assert_eq!(User::resolve(&headers("alice"), client, &[]), User("ip:192.0.2.1".to_string()));
+    }
+
+    #[test]
+    fn tells_the_clients_apart_without_the_header() {
+        let proxies: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+
+        assert_eq!(User::resolve(&headers(" "), Some("10.1.2.3".parse().unwrap()), &proxies).0, "ip:10.1.2.3");
+        assert_eq!(User::resolve(&HeaderMap::new(), Some("::1".parse().unwrap()), &[]).0, "ip:::1");
+        assert_eq!(User::resolve(&HeaderMap::new(), None, &[]).0, ANONYMOUS);
+    }
+
+    #[test]
+    fn takes_the_right_most_untrusted_forwarded_hop() {
+        let proxies: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+        let gateway = Some("10.1.2.3".parse().unwrap());
+        let forwarded = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(FORWARDED_FOR_HEADER, value.parse().unwrap());
+            headers
+        };
+
+        // The client prepends whatever it likes, only the hops added by the trusted proxies count.
+        let headers = forwarded("203.0.113.9, 192.0.2.1, 10.0.0.7");
+        assert_eq!(User::resolve(&headers, gateway, &proxies).0, "ip:192.0.2.1");
+        assert_eq!(User::resolve(&headers, None, &proxies).0, "ip:192.0.2.1");
+        assert_eq!(User::resolve(&forwarded("garbage, 10.0.0.7"), gateway, &proxies).0, "ip:10.1.2.3");
+        assert_eq!(User::resolve(&forwarded("10.0.0.7"), gateway, &proxies).0, "ip:10.1.2.3");
+
+        // From an untrusted peer the header is made up.
+        let client = Some("192.0.2.1".parse().unwrap());
+        assert_eq!(User::resolve(&forwarded("203.0.113.9"), client, &proxies).0, "ip:192.0.2.1");
+    }
+}
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use amp_common::http::HTTPError;
+use amp_common::scm::errors::SCMError;
 use url::Url;
 
 use crate::errors::{ApiError, Result};
@@ -30,3 +32,29 @@ pub fn unwrap_or_error<T>(option: Option<T>, error_message: &str) -> Result<T, A
         None => Err(ApiError::BadPlaybookRequest(error_message.to_string())),
     }
 }
+
+/// The SCM error of something the repository doesn't have, as if the SCM said so.
+pub fn missing(what: &str) -> SCMError {
+    SCMError::ClientError(HTTPError::NotFound(what.to_string()))
+}
+
+/// Whether the SCM says the repository doesn't have it, rather than failing to tell.
+pub fn is_missing(e: &SCMError) -> bool {
+    matches!(e, SCMError::ClientError(HTTPError::NotFound(_) | HTTPError::Transport(404, _)))
+}
+
+/// (De)serialize the bytes as a base64 string, rather than an array of numbers.
+pub mod base64 {
+    use ::base64::engine::general_purpose::STANDARD;
+    use ::base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
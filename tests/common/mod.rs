@@ -0,0 +1,54 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The helpers shared by the tests, a context with the upstreams faked by local servers.
+
+// Each test crate uses only some of the helpers.
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use axum::Router;
+use clap::Parser;
+use playground::config::Config;
+use playground::context::Context;
+use tokio::net::TcpListener;
+
+/// Serve the router on a random local port, returns its base URL.
+pub async fn serve(router: Router) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    format!("http://{}", addr)
+}
+
+/// The configuration with the defaults, GitHub served at the endpoint, and the extra arguments.
+pub fn config(github: &str, args: &[&str]) -> Config {
+    upstreams("http://127.0.0.1:9", github, args)
+}
+
+/// The configuration with the defaults, the Amphitheatre server and GitHub served at the endpoints,
+/// and the extra arguments.
+pub fn upstreams(amp_server: &str, github: &str, args: &[&str]) -> Config {
+    let data_dir = std::env::temp_dir().join(format!("playground-{}", uuid::Uuid::new_v4()));
+    let defaults = ["playground", "--port", "0", "--amp-server", amp_server, "--github-endpoint", github];
+    let data_dir = ["--data-dir", data_dir.to_str().unwrap()];
+
+    Config::parse_from(defaults.iter().chain(data_dir.iter()).chain(args.iter()))
+}
+
+pub async fn context(config: Config) -> Arc<Context> {
+    Arc::new(Context::new(config).await.unwrap())
+}
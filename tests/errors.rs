@@ -0,0 +1,357 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use amp_common::http::HTTPError;
+use amp_common::scm::errors::SCMError;
+use axum::http::header::{CONTENT_TYPE, RETRY_AFTER};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use playground::errors::{ApiError, ErrorCode, Problem, PROBLEM_CONTENT_TYPE, UPSTREAM_RETRY_AFTER};
+use playground::quota::Resource;
+use playground::responses::playbook::ValidationError;
+use uuid::Uuid;
+
+struct Case {
+    name: &'static str,
+    error: ApiError,
+    status: StatusCode,
+    code: ErrorCode,
+    retry_after: Option<u64>,
+}
+
+fn case(name: &'static str, error: ApiError, status: StatusCode, code: ErrorCode) -> Case {
+    Case { name, error, status, code, retry_after: None }
+}
+
+fn transport(code: u16) -> HTTPError {
+    HTTPError::Transport(code, format!("upstream responded with {}", code))
+}
+
+fn cases() -> Vec<Case> {
+    use ErrorCode as C;
+    use StatusCode as S;
+
+    vec![
+        case("internal server error", ApiError::InternalServerError, S::INTERNAL_SERVER_ERROR, C::InternalError),
+        case("not found", ApiError::NotFound, S::NOT_FOUND, C::NotFound),
+        case("method not allowed", ApiError::MethodNotAllowed, S::METHOD_NOT_ALLOWED, C::MethodNotAllowed),
+        // The rejections of the extractors keep their status.
+        case(
+            "invalid request",
+            ApiError::InvalidRequest(S::UNSUPPORTED_MEDIA_TYPE, "Expected JSON".into()),
+            S::UNSUPPORTED_MEDIA_TYPE,
+            C::InvalidRequest,
+        ),
+        case("payload too large", ApiError::PayloadTooLarge("1 MB".into()), S::PAYLOAD_TOO_LARGE, C::PayloadTooLarge),
+        case("request timeout", ApiError::RequestTimeout(30), S::GATEWAY_TIMEOUT, C::RequestTimeout),
+        // The upstream statuses are mapped faithfully.
+        case(
+            "playbook not found upstream",
+            ApiError::NotFoundPlaybook(HTTPError::NotFound("playbook".into())),
+            S::NOT_FOUND,
+            C::PlaybookNotFound,
+        ),
+        case("playbook 404", ApiError::NotFoundPlaybook(transport(404)), S::NOT_FOUND, C::PlaybookNotFound),
+        case(
+            "playbook unauthorized",
+            ApiError::NotFoundPlaybook(HTTPError::Unauthorized),
+            S::UNAUTHORIZED,
+            C::PlaybookNotFound,
+        ),
+        case("playbook upstream 500", ApiError::NotFoundPlaybook(transport(500)), S::BAD_GATEWAY, C::PlaybookNotFound),
+        case(
+            "create bad request",
+            ApiError::FailedToCreatePlaybook(HTTPError::BadRequest { message: "title".into(), attribute_errors: None }),
+            S::BAD_REQUEST,
+            C::PlaybookCreateFailed,
+        ),
+        case(
+            "create forbidden",
+            ApiError::FailedToCreatePlaybook(transport(403)),
+            S::FORBIDDEN,
+            C::PlaybookCreateFailed,
+        ),
+        Case {
+            retry_after: Some(UPSTREAM_RETRY_AFTER),
+            ..case(
+                "create rate limited",
+                ApiError::FailedToCreatePlaybook(transport(429)),
+                S::TOO_MANY_REQUESTS,
+                C::PlaybookCreateFailed,
+            )
+        },
+        case("update conflict", ApiError::FailedToUpdatePlaybook(transport(409)), S::CONFLICT, C::PlaybookUpdateFailed),
+        case(
+            "update gateway timeout",
+            ApiError::FailedToUpdatePlaybook(HTTPError::GatewayTimeout("timeout".into())),
+            S::GATEWAY_TIMEOUT,
+            C::PlaybookUpdateFailed,
+        ),
+        case(
+            "delete upstream 503",
+            ApiError::FailedToDeletePlaybook(transport(503)),
+            S::BAD_GATEWAY,
+            C::PlaybookDeleteFailed,
+        ),
+        case("delete 404", ApiError::FailedToDeletePlaybook(transport(404)), S::NOT_FOUND, C::PlaybookDeleteFailed),
+        case("start 401", ApiError::FailedToStartPlaybook(transport(401)), S::UNAUTHORIZED, C::PlaybookStartFailed),
+        case(
+            "start upstream 500",
+            ApiError::FailedToStartPlaybook(transport(500)),
+            S::BAD_GATEWAY,
+            C::PlaybookStartFailed,
+        ),
+        Case {
+            retry_after: Some(UPSTREAM_RETRY_AFTER),
+            ..case(
+                "start rate limited",
+                ApiError::FailedToStartPlaybook(transport(429)),
+                S::TOO_MANY_REQUESTS,
+                C::PlaybookStartFailed,
+            )
+        },
+        case(
+            "stop not implemented",
+            ApiError::FailedToStopPlaybook(HTTPError::NotImplemented("stop".into())),
+            S::BAD_GATEWAY,
+            C::PlaybookStopFailed,
+        ),
+        case(
+            "stop precondition",
+            ApiError::FailedToStopPlaybook(HTTPError::PreconditionRequired("stop".into())),
+            S::PRECONDITION_REQUIRED,
+            C::PlaybookStopFailed,
+        ),
+        case(
+            "sync method not allowed",
+            ApiError::FailedToSynchronize(HTTPError::MethodNotAllowed),
+            S::BAD_GATEWAY,
+            C::SyncFailed,
+        ),
+        case(
+            "sync deserialization",
+            ApiError::FailedToSynchronize(HTTPError::Deserialization("eof".into())),
+            S::BAD_GATEWAY,
+            C::SyncFailed,
+        ),
+        case("sync no response", ApiError::FailedToSynchronize(transport(0)), S::BAD_GATEWAY, C::SyncFailed),
+        case(
+            "content not found",
+            ApiError::NotFoundContent(SCMError::ClientError(HTTPError::NotFound("README.md".into()))),
+            S::NOT_FOUND,
+            C::ContentNotFound,
+        ),
+        // The caller may lack the access to a private repository.
+        case(
+            "content unauthorized",
+            ApiError::NotFoundContent(SCMError::ClientError(HTTPError::Unauthorized)),
+            S::UNAUTHORIZED,
+            C::ContentNotFound,
+        ),
+        case(
+            "content gateway timeout",
+            ApiError::NotFoundContent(SCMError::ClientError(HTTPError::GatewayTimeout("timeout".into()))),
+            S::GATEWAY_TIMEOUT,
+            C::ContentNotFound,
+        ),
+        case(
+            "invalid repo address",
+            ApiError::InvalidRepoAddress(url::ParseError::RelativeUrlWithoutBase),
+            S::BAD_REQUEST,
+            C::InvalidRepoAddress,
+        ),
+        case(
+            "folder not found",
+            ApiError::NotFoundFolder(SCMError::ClientError(transport(404))),
+            S::NOT_FOUND,
+            C::FolderNotFound,
+        ),
+        Case {
+            retry_after: Some(UPSTREAM_RETRY_AFTER),
+            ..case(
+                "folder rate limited",
+                ApiError::NotFoundFolder(SCMError::ClientError(transport(429))),
+                S::TOO_MANY_REQUESTS,
+                C::FolderNotFound,
+            )
+        },
+        case(
+            "folder unknown driver",
+            ApiError::NotFoundFolder(SCMError::UnknownDriver("svn".into())),
+            S::INTERNAL_SERVER_ERROR,
+            C::FolderNotFound,
+        ),
+        case("bad playbook", ApiError::BadPlaybook("no characters".into()), S::BAD_REQUEST, C::BadPlaybook),
+        case(
+            "repo not found",
+            ApiError::NotFoundRepo(SCMError::ClientError(transport(404))),
+            S::NOT_FOUND,
+            C::RepoNotFound,
+        ),
+        case(
+            "repo forbidden",
+            ApiError::NotFoundRepo(SCMError::ClientError(transport(403))),
+            S::FORBIDDEN,
+            C::RepoNotFound,
+        ),
+        Case {
+            retry_after: Some(UPSTREAM_RETRY_AFTER),
+            ..case(
+                "repo rate limited",
+                ApiError::NotFoundRepo(SCMError::ClientError(transport(429))),
+                S::TOO_MANY_REQUESTS,
+                C::RepoNotFound,
+            )
+        },
+        case(
+            "repo upstream 502",
+            ApiError::NotFoundRepo(SCMError::ClientError(transport(502))),
+            S::BAD_GATEWAY,
+            C::RepoNotFound,
+        ),
+        case(
+            "repo unknown driver",
+            ApiError::NotFoundRepo(SCMError::UnknownDriver("svn".into())),
+            S::INTERNAL_SERVER_ERROR,
+            C::RepoNotFound,
+        ),
+        case(
+            "bad playbook request",
+            ApiError::BadPlaybookRequest("no repo".into()),
+            S::BAD_REQUEST,
+            C::BadPlaybookRequest,
+        ),
+        case("bad search request", ApiError::BadSearchRequest("empty".into()), S::BAD_REQUEST, C::BadSearchRequest),
+        case(
+            "search invalid",
+            ApiError::FailedToSearchRepos(SCMError::ClientError(transport(422))),
+            S::UNPROCESSABLE_ENTITY,
+            C::RepoSearchFailed,
+        ),
+        case(
+            "search upstream 503",
+            ApiError::FailedToSearchRepos(SCMError::ClientError(transport(503))),
+            S::BAD_GATEWAY,
+            C::RepoSearchFailed,
+        ),
+        case(
+            "repo read upstream 500",
+            ApiError::FailedToReadRepo(SCMError::ClientError(transport(500))),
+            S::BAD_GATEWAY,
+            C::RepoReadFailed,
+        ),
+        case(
+            "invalid playbook",
+            ApiError::InvalidPlaybook(vec![ValidationError::new(".amp.toml", "missing")]),
+            S::UNPROCESSABLE_ENTITY,
+            C::InvalidPlaybook,
+        ),
+        case("template not found", ApiError::NotFoundTemplate("rust".into()), S::NOT_FOUND, C::TemplateNotFound),
+        case("snapshot not found", ApiError::NotFoundSnapshot("abc".into()), S::NOT_FOUND, C::SnapshotNotFound),
+        case(
+            "snapshot failed",
+            ApiError::FailedToSnapshot("too many".into()),
+            S::INTERNAL_SERVER_ERROR,
+            C::SnapshotFailed,
+        ),
+        case(
+            "provision failed",
+            ApiError::FailedToProvision("timeout".into()),
+            S::INTERNAL_SERVER_ERROR,
+            C::ProvisionFailed,
+        ),
+        case("operation not found", ApiError::NotFoundOperation(Uuid::nil()), S::NOT_FOUND, C::OperationNotFound),
+        case(
+            "idempotency key reused",
+            ApiError::IdempotencyKeyReused,
+            S::UNPROCESSABLE_ENTITY,
+            C::IdempotencyKeyReused,
+        ),
+        case("idempotency key in flight", ApiError::IdempotencyKeyInFlight, S::CONFLICT, C::IdempotencyKeyInFlight),
+        case(
+            "playbooks quota",
+            ApiError::QuotaExceeded { resource: Resource::Playbooks, used: 10, limit: 10 },
+            S::TOO_MANY_REQUESTS,
+            C::QuotaExceeded,
+        ),
+        case(
+            "workspace quota",
+            ApiError::QuotaExceeded { resource: Resource::WorkspaceBytes, used: 10, limit: 10 },
+            S::FORBIDDEN,
+            C::QuotaExceeded,
+        ),
+        case(
+            "running time quota",
+            ApiError::QuotaExceeded { resource: Resource::RunningSeconds, used: 10, limit: 10 },
+            S::FORBIDDEN,
+            C::QuotaExceeded,
+        ),
+        Case {
+            retry_after: Some(6),
+            ..case(
+                "rate limited",
+                ApiError::RateLimited { budget: "expensive", limit: 10, retry_after: 6 },
+                S::TOO_MANY_REQUESTS,
+                C::RateLimited,
+            )
+        },
+        // The reset is long past, the client is still asked to wait a moment.
+        Case {
+            retry_after: Some(1),
+            ..case(
+                "github rate limited",
+                ApiError::GitHubRateLimited { reset: 0 },
+                S::TOO_MANY_REQUESTS,
+                C::GitHubRateLimited,
+            )
+        },
+        Case {
+            retry_after: Some(30),
+            ..case(
+                "upstream unavailable",
+                ApiError::UpstreamUnavailable { service: "amphitheatre", retry_after: 30 },
+                S::SERVICE_UNAVAILABLE,
+                C::UpstreamUnavailable,
+            )
+        },
+    ]
+}
+
+#[test]
+fn maps_every_variant() {
+    for case in cases() {
+        assert_eq!(case.error.status(), case.status, "status of {}", case.name);
+        assert_eq!(case.error.code(), case.code, "code of {}", case.name);
+        assert_eq!(case.error.retry_after(), case.retry_after, "retry after of {}", case.name);
+    }
+}
+
+#[tokio::test]
+async fn responds_with_problems() {
+    for case in cases() {
+        let response = case.error.into_response();
+        assert_eq!(response.status(), case.status, "status of {}", case.name);
+        assert_eq!(response.headers()[CONTENT_TYPE], PROBLEM_CONTENT_TYPE, "content type of {}", case.name);
+
+        let retry_after = response.headers().get(RETRY_AFTER).map(|v| v.to_str().unwrap().parse().unwrap());
+        assert_eq!(retry_after, case.retry_after, "Retry-After of {}", case.name);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let problem: Problem = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem.status, case.status.as_u16(), "problem status of {}", case.name);
+        assert_eq!(problem.code, case.code, "problem code of {}", case.name);
+        assert!(problem.kind.ends_with(case.code.as_str()), "problem type of {}", case.name);
+        assert_eq!(problem.title, case.code.title(), "problem title of {}", case.name);
+    }
+}
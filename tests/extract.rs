@@ -0,0 +1,59 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::body::Body;
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use playground::errors::ErrorCode;
+use playground::extract::{Json, Query};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct Params {
+    #[allow(dead_code)]
+    page: u32,
+}
+
+fn json(content_type: &str, body: &'static str) -> Request {
+    Request::builder().method("POST").uri("/").header(CONTENT_TYPE, content_type).body(Body::from(body)).unwrap()
+}
+
+#[tokio::test]
+async fn rejects_bodies_with_problems() {
+    let error = Json::<Params>::from_request(json("text/plain", "{}"), &()).await.err().unwrap();
+    assert_eq!(error.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(error.code(), ErrorCode::InvalidRequest);
+
+    let error = Json::<Params>::from_request(json("application/json", "{"), &()).await.err().unwrap();
+    assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error.code(), ErrorCode::InvalidRequest);
+
+    let error = Json::<Params>::from_request(json("application/json", r#"{"page": "one"}"#), &()).await.err().unwrap();
+    assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error.code(), ErrorCode::InvalidRequest);
+
+    assert!(Json::<Params>::from_request(json("application/json", r#"{"page": 1}"#), &()).await.is_ok());
+}
+
+#[tokio::test]
+async fn rejects_queries_with_problems() {
+    let (mut parts, _) = Request::builder().uri("/?page=one").body(Body::empty()).unwrap().into_parts();
+    let error = Query::<Params>::from_request_parts(&mut parts, &()).await.err().unwrap();
+    assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error.code(), ErrorCode::InvalidRequest);
+
+    let (mut parts, _) = Request::builder().uri("/?page=1").body(Body::empty()).unwrap().into_parts();
+    assert!(Query::<Params>::from_request_parts(&mut parts, &()).await.is_ok());
+}
@@ -0,0 +1,152 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use axum::middleware::from_fn_with_state;
+use axum::routing::post;
+use axum::Router;
+use playground::errors::PROBLEM_CONTENT_TYPE;
+use playground::middleware::idempotency::{idempotency, IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAYED_HEADER};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
+
+/// The size of a response too large to be stored.
+const LARGE: usize = 17 * 1024 * 1024;
+
+/// Serve the routes behind the idempotency middleware, counting how many times the handlers run.
+async fn serve(calls: Arc<AtomicUsize>) -> String {
+    let ctx = common::context(common::config("http://127.0.0.1:9", &[])).await;
+    let count = move || calls.fetch_add(1, Ordering::SeqCst) + 1;
+    let router = Router::new()
+        .route(
+            "/count",
+            post({
+                let count = count.clone();
+                move || async move { count().to_string() }
+            }),
+        )
+        .route(
+            "/large",
+            post({
+                let count = count.clone();
+                move || async move { vec![count() as u8; LARGE] }
+            }),
+        )
+        .route(
+            "/reject",
+            post({
+                let count = count.clone();
+                move |body: String| async move {
+                    match body.as_str() {
+                        "{}" => (StatusCode::OK, count().to_string()),
+                        _ => (StatusCode::BAD_REQUEST, "expected an object".to_string()),
+                    }
+                }
+            }),
+        )
+        .route(
+            "/panic",
+            post(move || async move {
+                if count() == 1 {
+                    panic!("the first request fails midway");
+                }
+                "recovered"
+            }),
+        )
+        .layer(from_fn_with_state(ctx.clone(), idempotency))
+        .with_state(ctx);
+
+    common::serve(router).await
+}
+
+fn post_with_key(url: String, key: &str, body: &'static str) -> reqwest::RequestBuilder {
+    reqwest::Client::new().post(url).header(IDEMPOTENCY_KEY_HEADER, key).body(body)
+}
+
+#[tokio::test]
+async fn replays_the_first_response() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let base = serve(calls.clone()).await;
+
+    let first = post_with_key(format!("{}/count", base), "a", "{}").send().await.unwrap();
+    assert!(first.headers().get(IDEMPOTENT_REPLAYED_HEADER).is_none());
+    assert_eq!(first.text().await.unwrap(), "1");
+
+    let retry = post_with_key(format!("{}/count", base), "a", "{}").send().await.unwrap();
+    assert_eq!(retry.headers()[IDEMPOTENT_REPLAYED_HEADER], "true");
+    assert_eq!(retry.text().await.unwrap(), "1");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    let reused = post_with_key(format!("{}/count", base), "a", "[]").send().await.unwrap();
+    assert_eq!(reused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(reused.headers()[CONTENT_TYPE], PROBLEM_CONTENT_TYPE);
+
+    // Another query is another request too.
+    let reused = post_with_key(format!("{}/count?ref=main", base), "a", "{}").send().await.unwrap();
+    assert_eq!(reused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn lets_a_rejected_request_be_fixed() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let base = serve(calls.clone()).await;
+
+    let rejected = post_with_key(format!("{}/reject", base), "d", "[]").send().await.unwrap();
+    assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+
+    // The rejection is not stored, the fixed request is processed with the same key.
+    let fixed = post_with_key(format!("{}/reject", base), "d", "{}").send().await.unwrap();
+    assert_eq!(fixed.status(), StatusCode::OK);
+    assert_eq!(fixed.text().await.unwrap(), "1");
+}
+
+#[tokio::test]
+async fn passes_the_large_responses_through_unstored() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let base = serve(calls.clone()).await;
+
+    for expected in 1..=2u8 {
+        let response = post_with_key(format!("{}/large", base), "b", "{}").send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(IDEMPOTENT_REPLAYED_HEADER).is_none());
+        let body = response.bytes().await.unwrap();
+        assert_eq!(body.len(), LARGE);
+        assert!(body.iter().all(|b| *b == expected));
+    }
+}
+
+#[tokio::test]
+async fn forgets_the_key_of_a_request_which_never_completes() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let base = serve(calls.clone()).await;
+
+    assert!(post_with_key(format!("{}/panic", base), "c", "{}").send().await.is_err());
+
+    // The key is forgotten in the background once the request is dropped.
+    let mut retry = post_with_key(format!("{}/panic", base), "c", "{}").send().await.unwrap();
+    for _ in 0..50 {
+        if retry.status() != StatusCode::CONFLICT {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        retry = post_with_key(format!("{}/panic", base), "c", "{}").send().await.unwrap();
+    }
+    assert_eq!(retry.status(), StatusCode::OK);
+    assert_eq!(retry.text().await.unwrap(), "recovered");
+}
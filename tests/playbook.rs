@@ -0,0 +1,221 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use axum::body::{to_bytes, Body};
+use axum::extract::Request;
+use axum::http::{HeaderMap, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use opentelemetry::global;
+use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use playground::errors::ApiError;
+use playground::requests::playbook::CreatePlaybookRequest;
+use playground::services::{FileService, FolderService, PlaybookService};
+use serde_json::{json, Value};
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use uuid::Uuid;
+
+fn inline(files: &[(&str, &str)], language: Option<&str>) -> CreatePlaybookRequest {
+    let files: BTreeMap<String, String> = files.iter().map(|(p, c)| (p.to_string(), c.to_string())).collect();
+    CreatePlaybookRequest {
+        repo: None,
+        branch: None,
+        tag: None,
+        rev: None,
+        files: Some(files),
+        language: language.map(str::to_string),
+        ..CreatePlaybookRequest::default()
+    }
+}
+
+/// Serve a fake Amphitheatre server, which deploys the playbooks it creates at once and accepts the syncs.
+async fn amphitheatre() -> String {
+    let playbook: Arc<Mutex<Option<Value>>> = Arc::default();
+    let router = Router::new().fallback(move |req: Request| {
+        let playbook = playbook.clone();
+        async move {
+            let (method, path) = (req.method().clone(), req.uri().path().to_string());
+            let body = to_bytes(req.into_body(), usize::MAX).await.unwrap();
+            if path.ends_with("/sync") {
+                return StatusCode::OK.into_response();
+            }
+            if method == Method::POST && path.ends_with("/playbooks") {
+                let payload: Value = serde_json::from_slice(&body).unwrap();
+                let name = payload["preface"]["name"].clone();
+                let created = json!({
+                    "id": Uuid::new_v4().to_string(),
+                    "title": payload["title"],
+                    "description": payload["description"],
+                    "preface": payload["preface"],
+                    "characters": [{ "meta": { "name": name, "version": "0.0.1" } }],
+                });
+                *playbook.lock().unwrap() = Some(created.clone());
+                return Json(created).into_response();
+            }
+            match playbook.lock().unwrap().clone() {
+                Some(playbook) if method == Method::GET => Json(playbook).into_response(),
+                _ => Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()).unwrap(),
+            }
+        }
+    });
+
+    common::serve(router).await
+}
+
+#[tokio::test]
+async fn validates_the_inline_files() {
+    let ctx = common::context(common::config("http://127.0.0.1:9", &[])).await;
+
+    let report = PlaybookService::validate(ctx.clone(), &inline(&[("main.rs", "fn main() {}")], Some("rust"))).await;
+    assert!(report.unwrap().valid);
+
+    let report = PlaybookService::validate(ctx, &inline(&[("../main.rs", "fn main() {}")], None)).await.unwrap();
+    assert!(!report.valid);
+    assert_eq!(report.errors[0].field, "files");
+}
+
+#[tokio::test]
+async fn rejects_the_unknown_languages() {
+    let ctx = common::context(common::config("http://127.0.0.1:9", &[])).await;
+
+    let error = PlaybookService::validate(ctx.clone(), &inline(&[("main.cob", "")], Some("cobol"))).await.unwrap_err();
+    assert!(matches!(error, ApiError::BadPlaybookRequest(_)));
+
+    // The playbook is not created, the request is rejected before calling the Amphitheatre server.
+    let error = PlaybookService::create(ctx, &inline(&[("main.cob", "")], Some("cobol")), "alice").await.unwrap_err();
+    assert!(matches!(error, ApiError::BadPlaybookRequest(_)));
+}
+
+#[tokio::test]
+async fn rejects_both_repo_and_files() {
+    let ctx = common::context(common::config("http://127.0.0.1:9", &[])).await;
+    let req = CreatePlaybookRequest {
+        repo: Some("https://github.com/amphitheatre-app/amphitheatre.git".to_string()),
+        ..inline(&[("main.rs", "fn main() {}")], None)
+    };
+
+    let error = PlaybookService::validate(ctx.clone(), &req).await.unwrap_err();
+    assert!(matches!(error, ApiError::BadPlaybookRequest(_)));
+
+    let error = PlaybookService::create(ctx, &req, "alice").await.unwrap_err();
+    assert!(matches!(error, ApiError::BadPlaybookRequest(_)));
+}
+
+#[test]
+fn accepts_the_language_as_template() {
+    let req: CreatePlaybookRequest =
+        serde_json::from_str(r#"{"files": {"main.go": "package main"}, "template": "go"}"#).unwrap();
+    assert_eq!(req.language.as_deref(), Some("go"));
+}
+
+#[tokio::test]
+async fn rejects_the_fields_conflicting_with_the_template() {
+    let catalogue = std::env::temp_dir().join(format!("templates-{}.toml", uuid::Uuid::new_v4()));
+    let template = "[[templates]]\nid = \"rust\"\ntitle = \"Rust\"\nlanguage = \"rust\"\nfiles = { \"src/main.rs\" = \"fn main() {}\" }\n";
+    std::fs::write(&catalogue, template).unwrap();
+    let ctx =
+        common::context(common::config("http://127.0.0.1:9", &["--templates", catalogue.to_str().unwrap()])).await;
+
+    let req = CreatePlaybookRequest { template_id: Some("rust".to_string()), files: None, ..inline(&[], None) };
+    assert!(PlaybookService::validate(ctx.clone(), &req).await.unwrap().valid);
+
+    let req = CreatePlaybookRequest { template_id: Some("rust".to_string()), ..inline(&[("main.rs", "")], None) };
+    let error = PlaybookService::validate(ctx.clone(), &req).await.unwrap_err();
+    assert!(matches!(error, ApiError::BadPlaybookRequest(_)));
+
+    let req = CreatePlaybookRequest { template_id: Some("rust".to_string()), files: None, ..inline(&[], Some("go")) };
+    let error = PlaybookService::validate(ctx, &req).await.unwrap_err();
+    assert!(matches!(error, ApiError::BadPlaybookRequest(_)));
+}
+
+#[tokio::test]
+async fn reads_the_files_of_an_inline_playbook() {
+    let server = amphitheatre().await;
+    let ctx = common::context(common::upstreams(&server, "http://127.0.0.1:9", &[])).await;
+
+    let req = inline(&[("main.rs", "fn main() {}"), ("src/lib.rs", "")], Some("rust"));
+    let playbook = PlaybookService::create(ctx.clone(), &req, "alice").await.unwrap();
+    let id = Uuid::parse_str(&playbook.id).unwrap();
+
+    // The files are read back from the workspace changes, there is no repository to read them from.
+    let content = FileService::get(ctx.clone(), id, "main.rs".to_string()).await.unwrap();
+    assert_eq!(content.data, b"fn main() {}");
+    let error = FileService::get(ctx.clone(), id, "lib.rs".to_string()).await.unwrap_err();
+    assert!(matches!(error, ApiError::NotFoundContent(_)));
+
+    let files = FolderService::get(ctx.clone(), id, "src".to_string()).await.unwrap();
+    assert_eq!(files.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(), ["src/lib.rs"]);
+    let tree = FolderService::tree(ctx, id, None).await.unwrap();
+    assert_eq!(tree.tree.iter().map(|e| e.path.as_str()).collect::<Vec<_>>(), ["main.rs", "src"]);
+}
+
+#[tokio::test]
+async fn waits_through_the_upstream_failures() {
+    let (id, polls) = (Uuid::new_v4(), Arc::new(AtomicUsize::new(0)));
+    let playbook = json!({
+        "id": id.to_string(),
+        "title": "hello",
+        "preface": { "name": "hello", "live": false },
+        "characters": [{ "meta": { "name": "hello", "version": "0.1.0" } }],
+    });
+    let counted = polls.clone();
+    let router = Router::new().fallback(move || async move {
+        // The first poll times out, as when the Amphitheatre server is slow.
+        match counted.fetch_add(1, Ordering::SeqCst) {
+            0 => StatusCode::GATEWAY_TIMEOUT.into_response(),
+            _ => Json(playbook).into_response(),
+        }
+    });
+    let server = common::serve(router).await;
+    let ctx = common::context(common::upstreams(&server, "http://127.0.0.1:9", &["--upstream-retries", "0"])).await;
+
+    let playbook = PlaybookService::wait(ctx, &id.to_string()).await.unwrap();
+    assert_eq!(playbook.id, id.to_string());
+    assert_eq!(polls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn sends_the_trace_context_to_amphitheatre() {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let provider = TracerProvider::builder().build();
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let received: Arc<Mutex<Option<String>>> = Arc::default();
+    let recorded = received.clone();
+    let router = Router::new().fallback(move |headers: HeaderMap| async move {
+        *recorded.lock().unwrap() = headers.get("traceparent").and_then(|v| v.to_str().ok()).map(str::to_string);
+        StatusCode::NOT_FOUND
+    });
+    let server = common::serve(router).await;
+    let ctx = common::context(common::upstreams(&server, "http://127.0.0.1:9", &[])).await;
+
+    let span = tracing::info_span!("request");
+    let trace_id = span.context().span().span_context().trace_id();
+    let _ = ctx.upstream.playbook(&Uuid::new_v4().to_string()).instrument(span).await.unwrap();
+
+    let traceparent = received.lock().unwrap().clone().expect("the traceparent header is sent");
+    assert!(traceparent.contains(&trace_id.to_string()), "{} continues the trace {}", traceparent, trace_id);
+}
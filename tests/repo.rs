@@ -0,0 +1,125 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use playground::errors::{ApiError, ErrorCode};
+use playground::requests::repo::SearchRepositoryParams;
+use playground::services::RepoService;
+use serde_json::json;
+
+/// Answer the searches like GitHub, counting them.
+async fn search(
+    State(calls): State<Arc<AtomicUsize>>,
+    Query(params): Query<SearchRepositoryParams>,
+) -> impl IntoResponse {
+    calls.fetch_add(1, Ordering::SeqCst);
+    let (limit, resource, reset) = (("x-ratelimit-limit", "30"), ("x-ratelimit-resource", "search"), "4102444800");
+    let rate = [limit, ("x-ratelimit-remaining", "29"), ("x-ratelimit-reset", reset), resource];
+    match params.q.as_str() {
+        "axum" => (
+            StatusCode::OK,
+            rate,
+            Json(json!({
+                "total_count": 1,
+                "items": [{
+                    "full_name": "tokio-rs/axum",
+                    "description": "Ergonomic and modular web framework",
+                    "stargazers_count": 20000,
+                    "default_branch": "main",
+                    "clone_url": "https://github.com/tokio-rs/axum.git",
+                }],
+            })),
+        ),
+        "limited" => (StatusCode::FORBIDDEN, rate, Json(json!({ "message": "API rate limit exceeded" }))),
+        "exhausted" => (
+            StatusCode::FORBIDDEN,
+            [limit, ("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", reset), resource],
+            Json(json!({ "message": "API rate limit exceeded" })),
+        ),
+        "broken" => (StatusCode::SERVICE_UNAVAILABLE, rate, Json(json!({ "message": "Unavailable" }))),
+        _ => (StatusCode::UNPROCESSABLE_ENTITY, rate, Json(json!({ "message": "Validation Failed" }))),
+    }
+}
+
+fn params(q: &str) -> SearchRepositoryParams {
+    SearchRepositoryParams { q: q.to_string(), page: None, per_page: None }
+}
+
+async fn setup() -> (Arc<playground::context::Context>, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let github =
+        common::serve(Router::new().route("/search/repositories", get(search)).with_state(calls.clone())).await;
+
+    (common::context(common::config(&github, &[])).await, calls)
+}
+
+#[tokio::test]
+async fn searches_and_caches_the_results() {
+    let (ctx, calls) = setup().await;
+
+    let result = RepoService::search(ctx.clone(), &params("axum")).await.unwrap();
+    assert_eq!(result.total, 1);
+    assert_eq!((result.page, result.per_page), (1, 20));
+    assert_eq!(result.items[0].name, "tokio-rs/axum");
+    assert_eq!(result.items[0].url, "https://github.com/tokio-rs/axum.git");
+
+    RepoService::search(ctx, &params("axum")).await.unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn rejects_empty_queries() {
+    let (ctx, calls) = setup().await;
+
+    let error = RepoService::search(ctx, &params("  ")).await.unwrap_err();
+    assert!(matches!(error, ApiError::BadSearchRequest(_)));
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn maps_the_upstream_errors() {
+    let (ctx, _) = setup().await;
+
+    let error = RepoService::search(ctx.clone(), &params("stars:>")).await.unwrap_err();
+    assert_eq!((error.code(), error.status()), (ErrorCode::RepoSearchFailed, StatusCode::UNPROCESSABLE_ENTITY));
+
+    let error = RepoService::search(ctx.clone(), &params("broken")).await.unwrap_err();
+    assert_eq!((error.code(), error.status()), (ErrorCode::RepoSearchFailed, StatusCode::BAD_GATEWAY));
+
+    let error = RepoService::search(ctx, &params("limited")).await.unwrap_err();
+    assert_eq!((error.code(), error.status()), (ErrorCode::GitHubRateLimited, StatusCode::TOO_MANY_REQUESTS));
+}
+
+#[tokio::test]
+async fn keeps_the_search_rate_limit_apart() {
+    let (ctx, _) = setup().await;
+
+    for q in ["limited", "exhausted"] {
+        let error = RepoService::search(ctx.clone(), &params(q)).await.unwrap_err();
+        assert_eq!((error.code(), error.status()), (ErrorCode::GitHubRateLimited, StatusCode::TOO_MANY_REQUESTS));
+    }
+
+    // The token is still taken for the other calls, which count against the core rate limit.
+    let github = ctx.github.take().unwrap();
+    assert!(!github.status().exhausted);
+}
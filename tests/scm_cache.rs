@@ -0,0 +1,127 @@
+// Copyright (c) The Amphitheatre Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::header::{ETAG, IF_NONE_MATCH};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use playground::context::Context;
+use playground::errors::{ErrorCode, Result};
+use playground::scm_cache::Weigh;
+
+const SHA: &str = "0123456789abcdef0123456789abcdef01234567";
+
+/// How many times the fake GitHub resolved a branch, and answered that it's not modified.
+#[derive(Default)]
+struct Calls {
+    resolved: AtomicUsize,
+    not_modified: AtomicUsize,
+}
+
+/// Resolve the branches like GitHub, answering `304 Not Modified` to the matching `If-None-Match`.
+async fn commit(
+    State(calls): State<Arc<Calls>>,
+    Path((_, _, reference)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    calls.resolved.fetch_add(1, Ordering::SeqCst);
+    match reference.as_str() {
+        "feature/x" if headers.get(IF_NONE_MATCH).is_some_and(|v| v == "\"v1\"") => {
+            calls.not_modified.fetch_add(1, Ordering::SeqCst);
+            (StatusCode::NOT_MODIFIED, [(ETAG, "\"v1\"")]).into_response()
+        }
+        "feature/x" => (StatusCode::OK, [(ETAG, "\"v1\"")], SHA).into_response(),
+        "broken" => (StatusCode::SERVICE_UNAVAILABLE, "Unavailable").into_response(),
+        _ => (StatusCode::NOT_FOUND, "Not Found").into_response(),
+    }
+}
+
+async fn setup(ttl: &str) -> (Arc<Context>, Arc<Calls>) {
+    let calls = Arc::new(Calls::default());
+    let router = Router::new().route("/repos/{owner}/{name}/commits/{reference}", get(commit));
+    let github = common::serve(router.with_state(calls.clone())).await;
+
+    (common::context(common::config(&github, &["--scm-cache-ttl", ttl])).await, calls)
+}
+
+/// What a read returns, tagged with its kind and the commit it was read at.
+#[derive(Clone, Debug, PartialEq)]
+struct Read(String);
+
+impl Weigh for Read {
+    fn weigh(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Read through the cache, counting the reads which actually fetch.
+async fn read(ctx: &Context, kind: &'static str, reference: &str, fetched: &AtomicUsize) -> Result<String> {
+    let github = ctx.github.take()?;
+    ctx.scm_cache
+        .get(&github, kind, "octo/hello", reference, "README.md", |commit| async move {
+            fetched.fetch_add(1, Ordering::SeqCst);
+            Ok(Read(format!("{} at {}", kind, commit)))
+        })
+        .await
+        .map(|Read(read)| read)
+}
+
+#[tokio::test]
+async fn keeps_the_kinds_of_reads_apart() {
+    let (ctx, _) = setup("300").await;
+    let fetched = AtomicUsize::new(0);
+
+    assert_eq!(read(&ctx, "files", "feature/x", &fetched).await.unwrap(), format!("files at {}", SHA));
+    assert_eq!(read(&ctx, "folders", "feature/x", &fetched).await.unwrap(), format!("folders at {}", SHA));
+    assert_eq!(fetched.load(Ordering::SeqCst), 2);
+
+    assert_eq!(read(&ctx, "files", "feature/x", &fetched).await.unwrap(), format!("files at {}", SHA));
+    assert_eq!(read(&ctx, "files", SHA, &fetched).await.unwrap(), format!("files at {}", SHA));
+    assert_eq!(fetched.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn revalidates_the_branch_once_expired() {
+    let (ctx, calls) = setup("0").await;
+    let fetched = AtomicUsize::new(0);
+
+    read(&ctx, "files", "feature/x", &fetched).await.unwrap();
+    read(&ctx, "files", "feature/x", &fetched).await.unwrap();
+
+    assert_eq!(calls.resolved.load(Ordering::SeqCst), 2);
+    assert_eq!(calls.not_modified.load(Ordering::SeqCst), 1);
+    assert_eq!(fetched.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn reports_the_failures_to_resolve() {
+    let (ctx, _) = setup("300").await;
+    let fetched = AtomicUsize::new(0);
+
+    // A missing branch is read uncached, and the read tells it's missing.
+    assert_eq!(read(&ctx, "files", "missing", &fetched).await.unwrap(), "files at missing");
+    assert_eq!(fetched.load(Ordering::SeqCst), 1);
+
+    let error = read(&ctx, "files", "broken", &fetched).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::RepoReadFailed);
+    assert_eq!(error.status(), StatusCode::BAD_GATEWAY);
+    assert_eq!(fetched.load(Ordering::SeqCst), 1);
+}